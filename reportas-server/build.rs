@@ -1,4 +1,8 @@
 fn main() {
-    tonic_build::compile_protos("./src/proto/report.proto")
+    tonic_build::configure()
+        .compile(
+            &["./src/proto/report.proto", "./src/proto/report1_0.proto"],
+            &["./src/proto"],
+        )
         .unwrap_or_else(|e| panic!("Failed to compile protos {:?}", e));
 }
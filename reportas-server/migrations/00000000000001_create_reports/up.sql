@@ -1,5 +1,5 @@
 -- Your SQL goes here
-CREATE TABLE reports (
+CREATE TABLE IF NOT EXISTS reports (
     id BIGSERIAL PRIMARY KEY,
     active BOOL DEFAULT 't' NOT NULL,
     timestamp BIGINT NOT NULL,
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_server_idx;
+
+ALTER TABLE reports DROP COLUMN server;
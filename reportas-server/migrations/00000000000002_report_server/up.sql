@@ -0,0 +1,4 @@
+-- Identifier of the server node a report originates from.
+ALTER TABLE reports ADD COLUMN server TEXT;
+
+CREATE INDEX reports_server_idx ON reports (server);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE reports ADD COLUMN tags TEXT;
+
+UPDATE reports
+SET tags = (
+    SELECT string_agg(report_tags.tag, ',' ORDER BY report_tags.tag)
+    FROM report_tags
+    WHERE report_tags.report_id = reports.id
+);
+
+DROP TABLE report_tags;
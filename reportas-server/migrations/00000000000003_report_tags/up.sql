@@ -0,0 +1,16 @@
+-- Tags of a report, one row per tag.
+CREATE TABLE report_tags (
+    report_id BIGINT NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (report_id, tag)
+);
+
+CREATE INDEX report_tags_tag_idx ON report_tags (tag);
+
+-- Split the legacy comma-joined tags.
+INSERT INTO report_tags (report_id, tag)
+SELECT DISTINCT reports.id, trim(split.tag)
+FROM reports, unnest(string_to_array(reports.tags, ',')) AS split (tag)
+WHERE trim(split.tag) <> '';
+
+ALTER TABLE reports DROP COLUMN tags;
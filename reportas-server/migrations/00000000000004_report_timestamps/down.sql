@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE reports
+    ALTER COLUMN timestamp TYPE BIGINT USING extract(epoch FROM timestamp)::BIGINT,
+    ALTER COLUMN handle_ts TYPE BIGINT USING extract(epoch FROM handle_ts)::BIGINT;
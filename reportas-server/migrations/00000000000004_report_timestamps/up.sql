@@ -0,0 +1,4 @@
+-- Store timestamps as `timestamptz` instead of epoch seconds.
+ALTER TABLE reports
+    ALTER COLUMN timestamp TYPE TIMESTAMPTZ USING to_timestamp(timestamp),
+    ALTER COLUMN handle_ts TYPE TIMESTAMPTZ USING to_timestamp(handle_ts);
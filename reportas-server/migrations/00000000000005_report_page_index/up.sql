@@ -0,0 +1,2 @@
+-- Keyset pagination orders reports by insert time and id.
+CREATE INDEX reports_timestamp_id_idx ON reports (timestamp, id);
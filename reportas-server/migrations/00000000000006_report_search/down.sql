@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_search_idx;
+ALTER TABLE reports DROP COLUMN search;
@@ -0,0 +1,7 @@
+-- Full-text search over descriptions and comments, kept up to date by Postgres.
+ALTER TABLE reports ADD COLUMN search TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', description), 'A') ||
+    setweight(to_tsvector('english', coalesce(comment, '')), 'B')
+) STORED;
+
+CREATE INDEX reports_search_idx ON reports USING GIN (search);
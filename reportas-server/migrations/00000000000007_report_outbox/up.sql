@@ -0,0 +1,18 @@
+-- Report broadcasts written in the same transaction as the report change,
+-- delivered to the transporters by a background dispatcher.
+CREATE TABLE report_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    report_id BIGINT NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    -- Encoded `report.IdentifiedReportMessage` at the time of the change.
+    payload BYTEA NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Transporter endpoints already delivered to.
+    delivered_to TEXT[] NOT NULL DEFAULT '{}',
+    last_error TEXT,
+    created TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX report_outbox_next_attempt_idx ON report_outbox (next_attempt);
+CREATE INDEX report_outbox_report_id_idx ON report_outbox (report_id, id);
@@ -0,0 +1,12 @@
+-- Report transporters registered by game servers, broadcast to until
+-- their lease expires.
+CREATE TABLE transporters (
+    -- URI of the `ReportTransporter` service.
+    address TEXT PRIMARY KEY,
+    node TEXT NOT NULL,
+    registered TIMESTAMPTZ NOT NULL,
+    heartbeat TIMESTAMPTZ NOT NULL,
+    expires TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX transporters_expires_idx ON transporters (expires);
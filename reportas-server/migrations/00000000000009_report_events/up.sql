@@ -0,0 +1,31 @@
+-- Append-only history of every report state change.
+CREATE TABLE report_events (
+    id BIGSERIAL PRIMARY KEY,
+    report_id BIGINT NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    actor TEXT,
+    timestamp TIMESTAMPTZ NOT NULL,
+    -- Changed fields before and after the change, by name.
+    before JSONB,
+    after JSONB
+);
+
+CREATE INDEX report_events_report_id_idx ON report_events (report_id, timestamp, id);
+
+-- Reports from before the history only know their insert and latest deactivation.
+INSERT INTO report_events (report_id, kind, actor, timestamp, after)
+SELECT r.id, 'insert', r.reporter, r.timestamp, jsonb_build_object(
+    'reporter', r.reporter,
+    'reported', r.reported,
+    'description', r.description,
+    'tags', coalesce((SELECT jsonb_agg(t.tag ORDER BY t.tag) FROM report_tags t WHERE t.report_id = r.id), '[]'::jsonb),
+    'server', r.server,
+    'active', true)
+FROM reports r;
+
+INSERT INTO report_events (report_id, kind, actor, timestamp, before, after)
+SELECT r.id, 'deactivate', r.handler, coalesce(r.handle_ts, r.timestamp),
+    jsonb_build_object('active', true, 'handler', NULL, 'handle_ts', NULL),
+    jsonb_build_object('active', false, 'handler', r.handler, 'handle_ts', r.handle_ts)
+FROM reports r
+WHERE NOT r.active;
@@ -0,0 +1,2 @@
+-- Reason given by the actor of a change, such as for reopening a report.
+ALTER TABLE report_events ADD COLUMN reason TEXT;
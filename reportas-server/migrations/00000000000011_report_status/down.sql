@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_status_idx;
+
+ALTER TABLE reports DROP COLUMN active;
+ALTER TABLE reports ADD COLUMN active BOOL DEFAULT 't' NOT NULL;
+
+UPDATE reports SET active = status IN ('open', 'claimed', 'in_review');
+
+ALTER TABLE reports DROP COLUMN status;
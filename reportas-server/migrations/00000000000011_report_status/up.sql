@@ -0,0 +1,13 @@
+-- Moderation status of reports, replacing the active flag.
+ALTER TABLE reports ADD COLUMN status TEXT NOT NULL DEFAULT 'open'
+    CHECK (status IN ('open', 'claimed', 'in_review', 'resolved', 'rejected', 'duplicate'));
+
+UPDATE reports SET status = 'resolved' WHERE NOT active;
+
+-- The active flag of older clients is derived from the status.
+ALTER TABLE reports DROP COLUMN active;
+ALTER TABLE reports ADD COLUMN active BOOL GENERATED ALWAYS AS (
+    status IN ('open', 'claimed', 'in_review')
+) STORED;
+
+CREATE INDEX reports_status_idx ON reports (status);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_claim_expires_idx;
+ALTER TABLE reports DROP COLUMN claim_expires;
+ALTER TABLE reports DROP COLUMN claimant;
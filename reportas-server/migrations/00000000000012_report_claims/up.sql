@@ -0,0 +1,6 @@
+-- Handler a report is claimed by, until the claim expires.
+ALTER TABLE reports ADD COLUMN claimant TEXT;
+ALTER TABLE reports ADD COLUMN claim_expires TIMESTAMPTZ;
+
+CREATE INDEX reports_claim_expires_idx ON reports (claim_expires)
+    WHERE status IN ('claimed', 'in_review');
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_outcome_idx;
+ALTER TABLE reports DROP COLUMN outcome;
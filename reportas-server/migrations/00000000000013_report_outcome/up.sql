@@ -0,0 +1,6 @@
+-- Outcome of handling a report, unknown for reports closed before outcomes
+-- were recorded.
+ALTER TABLE reports ADD COLUMN outcome TEXT
+    CHECK (outcome IN ('punished', 'warned', 'no_action', 'false_report', 'duplicate', 'other'));
+
+CREATE INDEX reports_outcome_idx ON reports (outcome) WHERE outcome IS NOT NULL;
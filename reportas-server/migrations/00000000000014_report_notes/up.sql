@@ -0,0 +1,22 @@
+-- Running notes of moderators on reports.
+CREATE TABLE report_notes (
+    id BIGSERIAL PRIMARY KEY,
+    report_id BIGINT NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    author TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    edited TIMESTAMPTZ
+);
+
+CREATE INDEX report_notes_report_id_idx ON report_notes (report_id, id);
+
+-- Bodies of notes before each edit.
+CREATE TABLE report_note_edits (
+    id BIGSERIAL PRIMARY KEY,
+    note_id BIGINT NOT NULL REFERENCES report_notes (id) ON DELETE CASCADE,
+    editor TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    body TEXT NOT NULL
+);
+
+CREATE INDEX report_note_edits_note_id_idx ON report_note_edits (note_id, id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_original_idx;
+DROP INDEX reports_parent_id_idx;
+ALTER TABLE reports DROP COLUMN duplicates, DROP COLUMN parent_id;
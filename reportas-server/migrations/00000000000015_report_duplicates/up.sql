@@ -0,0 +1,10 @@
+-- Duplicates link to the report they duplicate, which counts them.
+ALTER TABLE reports
+    ADD COLUMN parent_id BIGINT REFERENCES reports (id) ON DELETE SET NULL,
+    ADD COLUMN duplicates INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX reports_parent_id_idx ON reports (parent_id) WHERE parent_id IS NOT NULL;
+
+-- Active originals of a reporter against a reported player, looked up on submit.
+CREATE INDEX reports_original_idx ON reports (reporter, reported, timestamp)
+    WHERE active AND parent_id IS NULL;
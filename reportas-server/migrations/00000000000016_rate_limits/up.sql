@@ -0,0 +1,10 @@
+-- Token buckets limiting report submission, shared by every instance.
+-- Buckets full again by `full_at` are as good as missing and get pruned.
+CREATE TABLE rate_limits (
+    bucket TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated TIMESTAMPTZ NOT NULL,
+    full_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX rate_limits_full_at_idx ON rate_limits (full_at);
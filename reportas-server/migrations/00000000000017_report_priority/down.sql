@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX reports_priority_idx;
+ALTER TABLE reports DROP COLUMN priority;
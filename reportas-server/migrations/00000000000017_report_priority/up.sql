@@ -0,0 +1,5 @@
+-- Priority of handling a report, computed on submit and recomputed as the
+-- inputs of the score change. Existing reports are scored by the next refresh.
+ALTER TABLE reports ADD COLUMN priority DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+CREATE INDEX reports_priority_idx ON reports (priority, id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX report_outbox_failed_at_idx;
+ALTER TABLE report_outbox DROP COLUMN failed_at;
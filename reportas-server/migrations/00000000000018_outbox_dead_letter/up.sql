@@ -0,0 +1,8 @@
+-- Outbox events which ran out of attempts are kept as dead letters instead
+-- of being rescheduled, and no longer hold back later events of the report.
+-- Events already past the attempt limit are failed by the dispatcher on
+-- their next attempt.
+ALTER TABLE report_outbox ADD COLUMN failed_at TIMESTAMPTZ;
+
+CREATE INDEX report_outbox_failed_at_idx ON report_outbox (failed_at)
+    WHERE failed_at IS NOT NULL;
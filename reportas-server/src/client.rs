@@ -4,8 +4,6 @@ use clap::{App, Arg};
 use report::report_handler_client::ReportHandlerClient;
 use report::{ReportMessage, ReportRequest};
 
-mod report_transporter;
-
 pub mod report {
     tonic::include_proto!("report");
 }
@@ -48,6 +46,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         reported: uuid::Uuid::new_v4().to_string(),
         desc: "joujou".into(),
         tags: "jeast,joust".into(),
+        server: "".into(),
     };
 
     for _ in 0..100 {
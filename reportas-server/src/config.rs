@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use service::{CacheMode, DuplicatePolicy, PriorityWeights, RateLimit};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("could not read config file: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("could not parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("invalid value for {0}")]
+    InvalidEnv(&'static str),
+    #[error("missing database url")]
+    MissingDatabaseUrl,
+}
+
+///
+/// Server configuration.
+///
+/// Read from a TOML file, every value of which can be overridden by an
+/// environment variable. Missing values fall back to their defaults.
+///
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the gRPC server listens on. `LISTEN_ADDR`
+    pub listen_addr: String,
+    /// Port the gRPC server listens on. `LISTEN_PORT`
+    pub listen_port: u16,
+
+    /// `DATABASE_URL`
+    pub database_url: Option<String>,
+    /// Largest amount of pooled database connections. `DATABASE_POOL_SIZE`
+    pub pool_size: u32,
+
+    /// Report transporters to forward reports to besides the registered
+    /// ones. `TRANSPORTER_ENDPOINTS`
+    pub endpoints: Vec<TransporterEndpoint>,
+
+    /// `CACHE_MODE`
+    pub cache: CacheMode,
+    /// Largest amount of cached reports. `CACHE_CAPACITY`
+    pub cache_capacity: usize,
+
+    pub timeouts: Timeouts,
+
+    pub duplicates: Duplicates,
+
+    pub rate_limits: RateLimits,
+
+    pub priority: Priority,
+}
+
+// The database URL may carry a password, so it is left out of logs.
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("listen_addr", &self.listen_addr)
+            .field("listen_port", &self.listen_port)
+            .field(
+                "database_url",
+                &self.database_url.as_deref().map(redact_password),
+            )
+            .field("pool_size", &self.pool_size)
+            .field("endpoints", &self.endpoints)
+            .field("cache", &self.cache)
+            .field("cache_capacity", &self.cache_capacity)
+            .field("timeouts", &self.timeouts)
+            .field("duplicates", &self.duplicates)
+            .field("rate_limits", &self.rate_limits)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+///
+/// A URL with the password of its user info, if any, replaced.
+///
+fn redact_password(url: &str) -> String {
+    let authority = match url.find("://") {
+        Some(at) => at + 3,
+        None => return url.to_owned(),
+    };
+
+    let rest = &url[authority..];
+    let host = rest.find(['/', '?']).unwrap_or(rest.len());
+
+    match rest[..host].rfind('@') {
+        Some(at) => match rest[..at].find(':') {
+            Some(colon) => format!("{}{}:***{}", &url[..authority], &rest[..colon], &rest[at..]),
+            None => url.to_owned(),
+        },
+        None => url.to_owned(),
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: "[::1]".to_owned(),
+            listen_port: 50051,
+            database_url: None,
+            pool_size: 10,
+            endpoints: vec![
+                TransporterEndpoint::Port(50024),
+                TransporterEndpoint::Port(50025),
+            ],
+            cache: CacheMode::Active,
+            cache_capacity: 100_000,
+            timeouts: Timeouts::default(),
+            duplicates: Duplicates::default(),
+            rate_limits: RateLimits::default(),
+            priority: Priority::default(),
+        }
+    }
+}
+
+///
+/// Timeouts in milliseconds.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Handling a single gRPC request. `REQUEST_TIMEOUT_MS`
+    pub request_ms: u64,
+    /// Checking out a pooled database connection. `DATABASE_TIMEOUT_MS`
+    pub database_ms: u64,
+    /// Connecting to a transporter. `TRANSPORT_CONNECT_TIMEOUT_MS`
+    pub transport_connect_ms: u64,
+    /// A single request to a transporter. `TRANSPORT_TIMEOUT_MS`
+    pub transport_ms: u64,
+    /// Lease of a registered transporter. `TRANSPORTER_LEASE_MS`
+    pub transporter_lease_ms: u64,
+    /// Claim of a report by a handler, released once run out. `CLAIM_LEASE_MS`
+    pub claim_lease_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            request_ms: 30_000,
+            database_ms: 5_000,
+            transport_connect_ms: 1_000,
+            transport_ms: 5_000,
+            transporter_lease_ms: 30_000,
+            claim_lease_ms: 900_000,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn request(&self) -> Duration {
+        Duration::from_millis(self.request_ms)
+    }
+
+    pub fn database(&self) -> Duration {
+        Duration::from_millis(self.database_ms)
+    }
+
+    pub fn transport_connect(&self) -> Duration {
+        Duration::from_millis(self.transport_connect_ms)
+    }
+
+    pub fn transport(&self) -> Duration {
+        Duration::from_millis(self.transport_ms)
+    }
+
+    pub fn transporter_lease(&self) -> Duration {
+        Duration::from_millis(self.transporter_lease_ms)
+    }
+
+    pub fn claim_lease(&self) -> Duration {
+        Duration::from_millis(self.claim_lease_ms)
+    }
+}
+
+///
+/// Detection of duplicate reports on submit.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Duplicates {
+    /// How long after an active report the same reporter reporting the
+    /// same player counts as a duplicate, 0 disabling detection.
+    /// `DUPLICATE_WINDOW_MS`
+    pub window_ms: u64,
+    /// Least share of words the descriptions have in common, from 0 to 1,
+    /// 0 ignoring descriptions. `DUPLICATE_SIMILARITY`
+    pub similarity: f64,
+}
+
+impl Default for Duplicates {
+    fn default() -> Self {
+        Self {
+            window_ms: 600_000,
+            similarity: 0.0,
+        }
+    }
+}
+
+impl Duplicates {
+    ///
+    /// Policy of the detection, `None` when disabled.
+    ///
+    pub fn policy(&self) -> Option<DuplicatePolicy> {
+        if self.window_ms == 0 {
+            return None;
+        }
+
+        Some(DuplicatePolicy {
+            window: Duration::from_millis(self.window_ms),
+            similarity: if self.similarity > 0.0 {
+                Some(self.similarity)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+///
+/// Limits on submitting reports, shared by every instance.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    /// Reports of a reporter. `RATE_LIMIT_REPORTER_CAPACITY`,
+    /// `RATE_LIMIT_REPORTER_PER_MINUTE`
+    pub reporter: RateLimit,
+    /// Reports of a reporter against a player. `RATE_LIMIT_PAIR_CAPACITY`,
+    /// `RATE_LIMIT_PAIR_PER_MINUTE`
+    pub pair: RateLimit,
+    /// Reports originating from a server node. `RATE_LIMIT_SERVER_CAPACITY`,
+    /// `RATE_LIMIT_SERVER_PER_MINUTE`
+    pub server: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            reporter: RateLimit {
+                capacity: 10,
+                per_minute: 5.0,
+            },
+            pair: RateLimit {
+                capacity: 3,
+                per_minute: 1.0,
+            },
+            server: RateLimit {
+                capacity: 600,
+                per_minute: 600.0,
+            },
+        }
+    }
+}
+
+///
+/// Scoring of the priority of reports.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Priority {
+    /// How far back distinct players reporting the same player are
+    /// counted. `PRIORITY_WINDOW_MS`
+    pub window_ms: u64,
+    /// Each distinct reporter besides the first. `PRIORITY_REPORTERS`
+    pub reporters: f64,
+    /// Reporters whose reports were all upheld, taken away for reporters
+    /// whose reports were all dismissed. `PRIORITY_ACCURACY`
+    pub accuracy: f64,
+    /// Each whole hour waited. `PRIORITY_WAITING_PER_HOUR`
+    pub waiting_per_hour: f64,
+    /// `PRIORITY_MAX_WAITING_HOURS`
+    pub max_waiting_hours: u32,
+    /// Weight of each tag. `PRIORITY_TAGS`, comma separated `tag=weight`
+    pub tags: HashMap<String, f64>,
+    /// How often every active report is rescored, 0 disabling it.
+    /// `PRIORITY_REFRESH_MS`
+    pub refresh_ms: u64,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            window_ms: 86_400_000,
+            reporters: 10.0,
+            accuracy: 20.0,
+            waiting_per_hour: 1.0,
+            max_waiting_hours: 48,
+            tags: HashMap::new(),
+            refresh_ms: 300_000,
+        }
+    }
+}
+
+impl Priority {
+    pub fn weights(&self) -> PriorityWeights {
+        PriorityWeights {
+            window: chrono::Duration::milliseconds(self.window_ms.min(i64::MAX as u64) as i64),
+            reporters: self.reporters,
+            accuracy: self.accuracy,
+            waiting_per_hour: self.waiting_per_hour,
+            max_waiting_hours: self.max_waiting_hours.into(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    ///
+    /// How often every active report is rescored, `None` when disabled.
+    ///
+    pub fn refresh(&self) -> Option<Duration> {
+        match self.refresh_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+}
+
+///
+/// Address of a report transporter.
+///
+/// A bare port stands for a transporter on `[::1]`.
+///
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum TransporterEndpoint {
+    Port(u16),
+    Uri(String),
+}
+
+impl TransporterEndpoint {
+    pub fn uri(&self) -> String {
+        match self {
+            TransporterEndpoint::Port(port) => format!("http://[::1]:{}", port),
+            TransporterEndpoint::Uri(uri) => uri.clone(),
+        }
+    }
+}
+
+impl FromStr for TransporterEndpoint {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u16>() {
+            Ok(port) => Ok(TransporterEndpoint::Port(port)),
+            Err(_) => Ok(TransporterEndpoint::Uri(s.to_owned())),
+        }
+    }
+}
+
+impl Config {
+    ///
+    /// Load the configuration from an optional TOML file, then apply
+    /// environment overrides.
+    ///
+    /// Variables in a `.env` file count as environment variables.
+    ///
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let mut config = match path {
+            Some(path) => toml::from_str(&std::fs::read_to_string(path)?)?,
+            None => Config::default(),
+        };
+
+        config.apply_env()?;
+
+        if config.database_url.is_none() {
+            return Err(Error::MissingDatabaseUrl);
+        }
+
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), Error> {
+        if let Some(val) = env("LISTEN_ADDR") {
+            self.listen_addr = val;
+        }
+
+        if let Some(val) = parse_env("LISTEN_PORT")? {
+            self.listen_port = val;
+        }
+
+        if let Some(val) = env("DATABASE_URL") {
+            self.database_url = Some(val);
+        }
+
+        if let Some(val) = parse_env("DATABASE_POOL_SIZE")? {
+            self.pool_size = val;
+        }
+
+        if let Some(val) = env("TRANSPORTER_ENDPOINTS") {
+            self.endpoints = val
+                .split(',')
+                .map(|endpoint| endpoint.trim())
+                .filter(|endpoint| !endpoint.is_empty())
+                .filter_map(|endpoint| endpoint.parse().ok())
+                .collect();
+        }
+
+        if let Some(val) = parse_env("CACHE_MODE")? {
+            self.cache = val;
+        }
+
+        if let Some(val) = parse_env("CACHE_CAPACITY")? {
+            self.cache_capacity = val;
+        }
+
+        if let Some(val) = parse_env("REQUEST_TIMEOUT_MS")? {
+            self.timeouts.request_ms = val;
+        }
+
+        if let Some(val) = parse_env("DATABASE_TIMEOUT_MS")? {
+            self.timeouts.database_ms = val;
+        }
+
+        if let Some(val) = parse_env("TRANSPORT_CONNECT_TIMEOUT_MS")? {
+            self.timeouts.transport_connect_ms = val;
+        }
+
+        if let Some(val) = parse_env("TRANSPORT_TIMEOUT_MS")? {
+            self.timeouts.transport_ms = val;
+        }
+
+        if let Some(val) = parse_env("TRANSPORTER_LEASE_MS")? {
+            self.timeouts.transporter_lease_ms = val;
+        }
+
+        if let Some(val) = parse_env("CLAIM_LEASE_MS")? {
+            self.timeouts.claim_lease_ms = val;
+        }
+
+        if let Some(val) = parse_env("DUPLICATE_WINDOW_MS")? {
+            self.duplicates.window_ms = val;
+        }
+
+        if let Some(val) = parse_env("DUPLICATE_SIMILARITY")? {
+            self.duplicates.similarity = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_REPORTER_CAPACITY")? {
+            self.rate_limits.reporter.capacity = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_REPORTER_PER_MINUTE")? {
+            self.rate_limits.reporter.per_minute = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_PAIR_CAPACITY")? {
+            self.rate_limits.pair.capacity = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_PAIR_PER_MINUTE")? {
+            self.rate_limits.pair.per_minute = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_SERVER_CAPACITY")? {
+            self.rate_limits.server.capacity = val;
+        }
+
+        if let Some(val) = parse_env("RATE_LIMIT_SERVER_PER_MINUTE")? {
+            self.rate_limits.server.per_minute = val;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_WINDOW_MS")? {
+            self.priority.window_ms = val;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_REPORTERS")? {
+            self.priority.reporters = val;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_ACCURACY")? {
+            self.priority.accuracy = val;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_WAITING_PER_HOUR")? {
+            self.priority.waiting_per_hour = val;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_MAX_WAITING_HOURS")? {
+            self.priority.max_waiting_hours = val;
+        }
+
+        if let Some(val) = env("PRIORITY_TAGS") {
+            self.priority.tags = val
+                .split(',')
+                .map(|weight| weight.trim())
+                .filter(|weight| !weight.is_empty())
+                .map(|weight| {
+                    let (tag, value) = weight
+                        .split_once('=')
+                        .ok_or(Error::InvalidEnv("PRIORITY_TAGS"))?;
+                    let value = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| Error::InvalidEnv("PRIORITY_TAGS"))?;
+
+                    Ok((tag.trim().to_owned(), value))
+                })
+                .collect::<Result<_, Error>>()?;
+        }
+
+        if let Some(val) = parse_env("PRIORITY_REFRESH_MS")? {
+            self.priority.refresh_ms = val;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Database URL, always present once loaded.
+    ///
+    pub fn database_url(&self) -> &str {
+        self.database_url.as_deref().unwrap_or_default()
+    }
+}
+
+fn env(key: &'static str) -> Option<String> {
+    dotenv::var(key).ok().filter(|val| !val.is_empty())
+}
+
+fn parse_env<T: FromStr>(key: &'static str) -> Result<Option<T>, Error> {
+    match env(key) {
+        Some(val) => val.parse().map(Some).map_err(|_| Error::InvalidEnv(key)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_database_password() {
+        assert_eq!(
+            redact_password("postgres://reportas:secret@db:5432/reportas"),
+            "postgres://reportas:***@db:5432/reportas"
+        );
+        assert_eq!(
+            redact_password("postgres://reportas@db/reportas?sslmode=require"),
+            "postgres://reportas@db/reportas?sslmode=require"
+        );
+        assert_eq!(
+            redact_password("postgres://db/reportas?user=a@b"),
+            "postgres://db/reportas?user=a@b"
+        );
+    }
+
+    #[test]
+    fn debug_leaves_out_database_password() {
+        let config = Config {
+            database_url: Some("postgres://reportas:secret@db/reportas".to_owned()),
+            ..Config::default()
+        };
+
+        assert!(!format!("{:?}", config).contains("secret"));
+    }
+}
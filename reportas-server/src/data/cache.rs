@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::models::Report;
+use crate::query::{ReportFilter, ReportFilterSet};
+
+///
+/// Reports a `ReportCache` is known to hold.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coverage {
+    /// Every report, so the cache answers any query.
+    All,
+    /// Every active report, so the cache answers queries for active reports.
+    Active,
+    /// Any subset of reports, so the cache only answers lookups by id.
+    Partial,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    report: Report,
+    used: u64,
+}
+
+///
+/// Bounded in-memory cache of reports, indexed by reporter, reported,
+/// handler and activity.
+///
+/// Once full, the least recently used report is evicted, inactive reports
+/// going before active ones. Evictions lower the coverage of the cache,
+/// which decides whether it can answer a query on its own.
+///
+#[derive(Debug)]
+pub struct ReportCache {
+    capacity: usize,
+    coverage: Coverage,
+    reports: HashMap<i64, Entry>,
+    used: u64,
+
+    /// Ids by last use, least recently used first.
+    inactive_lru: BTreeMap<u64, i64>,
+    active_lru: BTreeMap<u64, i64>,
+
+    by_reporter: HashMap<String, HashSet<i64>>,
+    by_reported: HashMap<String, HashSet<i64>>,
+    by_handler: HashMap<Option<String>, HashSet<i64>>,
+    by_active: HashMap<bool, HashSet<i64>>,
+}
+
+impl ReportCache {
+    ///
+    /// Empty cache of at most `capacity` reports.
+    ///
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            coverage: Coverage::Partial,
+            reports: HashMap::new(),
+            used: 0,
+            inactive_lru: BTreeMap::new(),
+            active_lru: BTreeMap::new(),
+            by_reporter: HashMap::new(),
+            by_reported: HashMap::new(),
+            by_handler: HashMap::new(),
+            by_active: HashMap::new(),
+        }
+    }
+
+    pub fn coverage(&self) -> Coverage {
+        self.coverage
+    }
+
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    ///
+    /// Replace the cached reports with reports of the given coverage.
+    ///
+    /// Later reports count as more recently used.
+    ///
+    pub fn warm(&mut self, coverage: Coverage, reports: Vec<Report>) {
+        self.clear();
+        self.coverage = coverage;
+
+        for report in reports {
+            self.put(report);
+        }
+    }
+
+    ///
+    /// Cache the current state of a report, evicting others if full.
+    ///
+    pub fn put(&mut self, report: Report) {
+        if self.capacity == 0 {
+            self.coverage = Coverage::Partial;
+            return;
+        }
+
+        self.remove(report.id);
+
+        let used = self.tick();
+        let id = report.id;
+
+        self.lru(report.active).insert(used, id);
+
+        index(&mut self.by_reporter, report.reporter.clone(), id);
+        index(&mut self.by_reported, report.reported.clone(), id);
+        index(&mut self.by_handler, report.handler.clone(), id);
+        index(&mut self.by_active, report.active, id);
+
+        self.reports.insert(id, Entry { report, used });
+
+        while self.reports.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    ///
+    /// Reports matching every filter of a filter set, or `None` when the
+    /// cache cannot tell whether it holds all of them.
+    ///
+    /// The returned reports count as used.
+    ///
+    pub fn query(&mut self, filters: &ReportFilterSet) -> Option<Vec<Report>> {
+        if filters.search().is_some() || !self.covers(filters) {
+            return None;
+        }
+
+        let mut ids: Vec<i64> = self
+            .candidates(filters)
+            .into_iter()
+            .filter(|id| match self.reports.get(id) {
+                Some(entry) => filters.matches(&entry.report),
+                None => false,
+            })
+            .collect();
+
+        ids.sort_unstable();
+
+        let res = ids
+            .into_iter()
+            .map(|id| {
+                self.touch(id);
+                self.reports[&id].report.clone()
+            })
+            .collect();
+
+        Some(res)
+    }
+
+    ///
+    /// Whether every report matching the filters is cached.
+    ///
+    fn covers(&self, filters: &ReportFilterSet) -> bool {
+        let cached_id = filters.filters.iter().any(|filter| match filter {
+            ReportFilter::Id(id) => self.reports.contains_key(id),
+            _ => false,
+        });
+
+        let only_active = filters.filters.iter().any(|filter| match filter {
+            ReportFilter::Active(value) => *value,
+            ReportFilter::Status(values) => values.iter().all(|x| x.is_active()),
+            _ => false,
+        });
+
+        match self.coverage {
+            _ if cached_id => true,
+            Coverage::All => true,
+            Coverage::Active => only_active,
+            Coverage::Partial => false,
+        }
+    }
+
+    ///
+    /// Ids of the smallest index matching one of the filters, every id
+    /// when no filter is indexed.
+    ///
+    fn candidates(&self, filters: &ReportFilterSet) -> Vec<i64> {
+        let empty = HashSet::new();
+        let mut smallest: Option<&HashSet<i64>> = None;
+
+        for filter in filters.filters.iter() {
+            let ids = match filter {
+                ReportFilter::Id(id) => return vec![*id],
+                ReportFilter::Reporter(value) => self.by_reporter.get(value),
+                ReportFilter::Reported(value) => self.by_reported.get(value),
+                ReportFilter::Handler(value) => self.by_handler.get(value),
+                ReportFilter::Active(value) => self.by_active.get(value),
+                _ => continue,
+            };
+
+            let ids = ids.unwrap_or(&empty);
+
+            match smallest {
+                Some(val) if val.len() <= ids.len() => {}
+                _ => smallest = Some(ids),
+            }
+        }
+
+        match smallest {
+            Some(ids) => ids.iter().copied().collect(),
+            None => self.reports.keys().copied().collect(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.used += 1;
+        self.used
+    }
+
+    fn lru(&mut self, active: bool) -> &mut BTreeMap<u64, i64> {
+        if active {
+            &mut self.active_lru
+        } else {
+            &mut self.inactive_lru
+        }
+    }
+
+    fn touch(&mut self, id: i64) {
+        let used = self.tick();
+
+        let (active, last) = match self.reports.get_mut(&id) {
+            Some(entry) => (
+                entry.report.active,
+                std::mem::replace(&mut entry.used, used),
+            ),
+            None => return,
+        };
+
+        let lru = self.lru(active);
+        lru.remove(&last);
+        lru.insert(used, id);
+    }
+
+    ///
+    /// Drop every cached report, falling back to `Coverage::Partial`.
+    ///
+    pub fn clear(&mut self) {
+        *self = Self::new(self.capacity);
+    }
+
+    pub fn remove(&mut self, id: i64) -> Option<Report> {
+        let Entry { report, used } = self.reports.remove(&id)?;
+
+        self.lru(report.active).remove(&used);
+
+        unindex(&mut self.by_reporter, &report.reporter, id);
+        unindex(&mut self.by_reported, &report.reported, id);
+        unindex(&mut self.by_handler, &report.handler, id);
+        unindex(&mut self.by_active, &report.active, id);
+
+        Some(report)
+    }
+
+    ///
+    /// Evict the least recently used report, lowering the coverage.
+    ///
+    fn evict(&mut self) {
+        if let Some(&id) = self.inactive_lru.values().next() {
+            self.remove(id);
+
+            if self.coverage == Coverage::All {
+                self.coverage = Coverage::Active;
+            }
+        } else if let Some(&id) = self.active_lru.values().next() {
+            self.remove(id);
+            self.coverage = Coverage::Partial;
+        }
+    }
+}
+
+fn index<K: Hash + Eq>(index: &mut HashMap<K, HashSet<i64>>, key: K, id: i64) {
+    index.entry(key).or_default().insert(id);
+}
+
+fn unindex<K: Hash + Eq>(index: &mut HashMap<K, HashSet<i64>>, key: &K, id: i64) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(&id);
+
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ReportStatus;
+
+    fn report(id: i64, active: bool, handler: Option<&str>) -> Report {
+        Report {
+            active,
+            status: if active {
+                ReportStatus::Open
+            } else {
+                ReportStatus::Resolved
+            },
+            handler: handler.map(str::to_owned),
+            ..Report::fixture(id)
+        }
+    }
+
+    fn ids(reports: Option<Vec<Report>>) -> Option<Vec<i64>> {
+        reports.map(|reports| reports.into_iter().map(|report| report.id).collect())
+    }
+
+    #[test]
+    fn evicting_inactive_report_drops_all_to_active() {
+        let mut cache = ReportCache::new(2);
+        cache.warm(
+            Coverage::All,
+            vec![report(1, false, Some("mod")), report(2, true, None)],
+        );
+
+        cache.put(report(3, true, None));
+
+        assert_eq!(cache.coverage(), Coverage::Active);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.reports.contains_key(&1));
+    }
+
+    #[test]
+    fn evicting_active_report_drops_active_to_partial() {
+        let mut cache = ReportCache::new(2);
+        cache.warm(
+            Coverage::Active,
+            vec![report(1, true, None), report(2, true, None)],
+        );
+
+        cache.put(report(3, true, None));
+
+        assert_eq!(cache.coverage(), Coverage::Partial);
+        assert!(!cache.reports.contains_key(&1));
+    }
+
+    #[test]
+    fn inactive_reports_are_evicted_first() {
+        let mut cache = ReportCache::new(2);
+        cache.warm(
+            Coverage::All,
+            vec![report(1, true, None), report(2, false, Some("mod"))],
+        );
+
+        cache.put(report(3, true, None));
+
+        assert!(cache.reports.contains_key(&1));
+        assert!(!cache.reports.contains_key(&2));
+    }
+
+    #[test]
+    fn query_needs_coverage() {
+        let active = ReportFilterSet::all().with(ReportFilter::Active(true));
+
+        let mut cache = ReportCache::new(10);
+        cache.warm(
+            Coverage::Active,
+            vec![report(1, true, None), report(2, true, None)],
+        );
+
+        assert_eq!(cache.query(&ReportFilterSet::all()), None);
+        assert_eq!(
+            cache.query(&ReportFilterSet::all().with(ReportFilter::Active(false))),
+            None
+        );
+        assert_eq!(ids(cache.query(&active)), Some(vec![1, 2]));
+
+        cache.warm(Coverage::Partial, vec![report(1, true, None)]);
+
+        assert_eq!(cache.query(&active), None);
+        assert_eq!(
+            ids(cache.query(&ReportFilterSet::all().with(ReportFilter::Id(1)))),
+            Some(vec![1])
+        );
+        assert_eq!(
+            cache.query(&ReportFilterSet::all().with(ReportFilter::Id(2))),
+            None
+        );
+    }
+
+    #[test]
+    fn query_skips_search() {
+        let mut cache = ReportCache::new(10);
+        cache.warm(Coverage::All, vec![report(1, true, None)]);
+
+        let search = ReportFilterSet::all().with(ReportFilter::Search("cheat".to_owned()));
+
+        assert_eq!(cache.query(&search), None);
+    }
+
+    #[test]
+    fn remove_cleans_up_indexes() {
+        let mut cache = ReportCache::new(10);
+        cache.warm(Coverage::All, vec![report(1, true, Some("mod"))]);
+
+        assert!(cache.remove(1).is_some());
+
+        assert!(cache.is_empty());
+        assert!(cache.active_lru.is_empty());
+        assert!(cache.by_reporter.is_empty());
+        assert!(cache.by_reported.is_empty());
+        assert!(cache.by_handler.is_empty());
+        assert!(cache.by_active.is_empty());
+    }
+
+    #[test]
+    fn put_reindexes_changed_report() {
+        let mut cache = ReportCache::new(10);
+        cache.warm(Coverage::All, vec![report(1, true, None)]);
+
+        cache.put(report(1, false, Some("mod")));
+
+        assert_eq!(cache.len(), 1);
+        assert!(cache.active_lru.is_empty());
+        assert_eq!(cache.inactive_lru.len(), 1);
+        assert!(!cache.by_active.contains_key(&true));
+        assert!(!cache.by_handler.contains_key(&None));
+
+        let handled = ReportFilterSet::all().with(ReportFilter::Handler(Some("mod".to_owned())));
+        let unhandled = ReportFilterSet::all().with(ReportFilter::Handler(None));
+        let active = ReportFilterSet::all().with(ReportFilter::Active(true));
+
+        assert_eq!(ids(cache.query(&handled)), Some(vec![1]));
+        assert_eq!(ids(cache.query(&unhandled)), Some(vec![]));
+        assert_eq!(ids(cache.query(&active)), Some(vec![]));
+    }
+}
@@ -1,2 +1,8 @@
+pub mod cache;
 pub mod models;
+pub mod notify;
+pub mod priority;
+pub mod query;
+pub mod rate_limit;
 pub mod schema;
+pub mod search;
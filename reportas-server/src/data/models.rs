@@ -1,30 +1,472 @@
-use crate::schema::reports;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, SubsecRound, TimeZone, Utc};
+
+use serde_json::{json, Map, Value};
+
+use crate::schema::{
+    report_events, report_note_edits, report_notes, report_outbox, report_tags, reports,
+    transporters,
+};
 
 use crate::report;
+use crate::report1_0;
 
+///
+/// A row of the `reports` table, without its tags.
+///
 #[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct ReportRow {
+    pub id: i64,
+    pub active: bool,
+    pub timestamp: DateTime<Utc>,
+
+    pub reporter: String,
+    pub reported: String,
+
+    pub handler: Option<String>,
+    pub handle_ts: Option<DateTime<Utc>>,
+    pub comment: Option<String>,
+
+    pub description: String,
+
+    pub server: Option<String>,
+    pub status: String,
+
+    pub claimant: Option<String>,
+    pub claim_expires: Option<DateTime<Utc>>,
+
+    pub outcome: Option<String>,
+
+    pub parent_id: Option<i64>,
+    pub duplicates: i32,
+
+    pub priority: f64,
+}
+
+impl ReportRow {
+    ///
+    /// Status of the report, derived from `active` should the stored one
+    /// be unknown.
+    ///
+    pub fn report_status(&self) -> ReportStatus {
+        match self.status.parse() {
+            Ok(val) => val,
+            Err(_) => ReportStatus::legacy(self.active),
+        }
+    }
+
+    ///
+    /// Handler holding a claim on the report that has not run out at the
+    /// given time.
+    ///
+    pub fn live_claimant(&self, at: DateTime<Utc>) -> Option<&str> {
+        match (&self.claimant, self.claim_expires) {
+            (Some(claimant), Some(expires)) if expires > at => Some(claimant),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Status of the report at the given time, claimed reports falling
+    /// back to open once their claim runs out.
+    ///
+    pub fn status_at(&self, at: DateTime<Utc>) -> ReportStatus {
+        match self.report_status() {
+            ReportStatus::Claimed | ReportStatus::InReview if self.live_claimant(at).is_none() => {
+                ReportStatus::Open
+            }
+            status => status,
+        }
+    }
+}
+
+///
+/// Moderation status of a report.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportStatus {
+    Open,
+    Claimed,
+    InReview,
+    Resolved,
+    Rejected,
+    Duplicate,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Claimed => "claimed",
+            ReportStatus::InReview => "in_review",
+            ReportStatus::Resolved => "resolved",
+            ReportStatus::Rejected => "rejected",
+            ReportStatus::Duplicate => "duplicate",
+        }
+    }
+
+    ///
+    /// Status of a report known only by its legacy `active` flag.
+    ///
+    pub fn legacy(active: bool) -> Self {
+        if active {
+            ReportStatus::Open
+        } else {
+            ReportStatus::Resolved
+        }
+    }
+
+    ///
+    /// Whether reports of the status still await handling, which older
+    /// clients know as `active`.
+    ///
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            ReportStatus::Open | ReportStatus::Claimed | ReportStatus::InReview
+        )
+    }
+
+    ///
+    /// Whether a report may move from this status to another.
+    ///
+    /// Reports are claimed before going into review, and may be handed
+    /// back at any point. Active reports close into any inactive status,
+    /// while closed reports can only be reopened.
+    ///
+    pub fn can_transition_to(&self, to: ReportStatus) -> bool {
+        match (self, to) {
+            (ReportStatus::Open, ReportStatus::Claimed) => true,
+            (ReportStatus::Claimed, ReportStatus::Open | ReportStatus::InReview) => true,
+            (ReportStatus::InReview, ReportStatus::Open | ReportStatus::Claimed) => true,
+            (from, to) if from.is_active() => !to.is_active(),
+            (_, to) => to == ReportStatus::Open,
+        }
+    }
+}
+
+impl fmt::Display for ReportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ReportStatus {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(ReportStatus::Open),
+            "claimed" => Ok(ReportStatus::Claimed),
+            "in_review" => Ok(ReportStatus::InReview),
+            "resolved" => Ok(ReportStatus::Resolved),
+            "rejected" => Ok(ReportStatus::Rejected),
+            "duplicate" => Ok(ReportStatus::Duplicate),
+            _ => Err("invalid report status"),
+        }
+    }
+}
+
+impl From<ReportStatus> for report1_0::ReportStatus {
+    fn from(f: ReportStatus) -> Self {
+        match f {
+            ReportStatus::Open => report1_0::ReportStatus::Open,
+            ReportStatus::Claimed => report1_0::ReportStatus::Claimed,
+            ReportStatus::InReview => report1_0::ReportStatus::InReview,
+            ReportStatus::Resolved => report1_0::ReportStatus::Resolved,
+            ReportStatus::Rejected => report1_0::ReportStatus::Rejected,
+            ReportStatus::Duplicate => report1_0::ReportStatus::Duplicate,
+        }
+    }
+}
+
+impl TryFrom<report1_0::ReportStatus> for ReportStatus {
+    type Error = &'static str;
+
+    fn try_from(f: report1_0::ReportStatus) -> Result<Self, Self::Error> {
+        match f {
+            report1_0::ReportStatus::Unspecified => Err("missing report status"),
+            report1_0::ReportStatus::Open => Ok(ReportStatus::Open),
+            report1_0::ReportStatus::Claimed => Ok(ReportStatus::Claimed),
+            report1_0::ReportStatus::InReview => Ok(ReportStatus::InReview),
+            report1_0::ReportStatus::Resolved => Ok(ReportStatus::Resolved),
+            report1_0::ReportStatus::Rejected => Ok(ReportStatus::Rejected),
+            report1_0::ReportStatus::Duplicate => Ok(ReportStatus::Duplicate),
+        }
+    }
+}
+
+///
+/// Outcome of handling a report.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResolutionOutcome {
+    /// The reported player was punished.
+    Punished,
+    /// The reported player was warned.
+    Warned,
+    NoAction,
+    /// The report was made in bad faith.
+    FalseReport,
+    /// The report was already made.
+    Duplicate,
+    Other,
+}
+
+impl ResolutionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResolutionOutcome::Punished => "punished",
+            ResolutionOutcome::Warned => "warned",
+            ResolutionOutcome::NoAction => "no_action",
+            ResolutionOutcome::FalseReport => "false_report",
+            ResolutionOutcome::Duplicate => "duplicate",
+            ResolutionOutcome::Other => "other",
+        }
+    }
+}
+
+impl FromStr for ResolutionOutcome {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "punished" => Ok(ResolutionOutcome::Punished),
+            "warned" => Ok(ResolutionOutcome::Warned),
+            "no_action" => Ok(ResolutionOutcome::NoAction),
+            "false_report" => Ok(ResolutionOutcome::FalseReport),
+            "duplicate" => Ok(ResolutionOutcome::Duplicate),
+            "other" => Ok(ResolutionOutcome::Other),
+            _ => Err("invalid resolution outcome"),
+        }
+    }
+}
+
+impl From<ResolutionOutcome> for report1_0::ResolutionOutcome {
+    fn from(f: ResolutionOutcome) -> Self {
+        match f {
+            ResolutionOutcome::Punished => report1_0::ResolutionOutcome::Punished,
+            ResolutionOutcome::Warned => report1_0::ResolutionOutcome::Warned,
+            ResolutionOutcome::NoAction => report1_0::ResolutionOutcome::NoAction,
+            ResolutionOutcome::FalseReport => report1_0::ResolutionOutcome::FalseReport,
+            ResolutionOutcome::Duplicate => report1_0::ResolutionOutcome::Duplicate,
+            ResolutionOutcome::Other => report1_0::ResolutionOutcome::Other,
+        }
+    }
+}
+
+///
+/// Convert a `report1_0.ResolutionOutcome` value to a `ResolutionOutcome`,
+/// `None` when unspecified.
+///
+pub fn from_proto_outcome(outcome: i32) -> Result<Option<ResolutionOutcome>, &'static str> {
+    match report1_0::ResolutionOutcome::from_i32(outcome) {
+        Some(report1_0::ResolutionOutcome::Unspecified) => Ok(None),
+        Some(report1_0::ResolutionOutcome::Punished) => Ok(Some(ResolutionOutcome::Punished)),
+        Some(report1_0::ResolutionOutcome::Warned) => Ok(Some(ResolutionOutcome::Warned)),
+        Some(report1_0::ResolutionOutcome::NoAction) => Ok(Some(ResolutionOutcome::NoAction)),
+        Some(report1_0::ResolutionOutcome::FalseReport) => Ok(Some(ResolutionOutcome::FalseReport)),
+        Some(report1_0::ResolutionOutcome::Duplicate) => Ok(Some(ResolutionOutcome::Duplicate)),
+        Some(report1_0::ResolutionOutcome::Other) => Ok(Some(ResolutionOutcome::Other)),
+        None => Err("invalid resolution outcome"),
+    }
+}
+
+///
+/// Convert a `report1_0.ReportStatus` value to a `ReportStatus`.
+///
+pub fn from_proto_status(status: i32) -> Result<ReportStatus, &'static str> {
+    match report1_0::ReportStatus::from_i32(status) {
+        Some(val) => ReportStatus::try_from(val),
+        None => Err("invalid report status"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Report {
     pub id: i64,
+    /// Derived from the status.
     pub active: bool,
-    pub timestamp: i64,
+    pub status: ReportStatus,
+    pub timestamp: DateTime<Utc>,
 
     pub reporter: String,
     pub reported: String,
 
     pub handler: Option<String>,
-    pub handle_ts: Option<i64>,
+    pub handle_ts: Option<DateTime<Utc>>,
     pub comment: Option<String>,
 
     pub description: String,
-    pub tags: Option<String>,
+    pub tags: Vec<String>,
+
+    pub server: Option<String>,
+
+    /// Handler the report is claimed by, until `claim_expires`.
+    pub claimant: Option<String>,
+    pub claim_expires: Option<DateTime<Utc>>,
+
+    /// Outcome of handling the report, `None` while active or when unknown.
+    pub outcome: Option<ResolutionOutcome>,
+
+    /// Report this one is a duplicate of.
+    pub parent: Option<i64>,
+    /// Amount of reports merged into this one, theirs included.
+    pub duplicates: i32,
+
+    /// Priority of handling the report, see `priority::PriorityWeights`.
+    pub priority: f64,
 }
 
-impl From<report::IdentifiedReportMessage> for Report {
-    fn from(f: report::IdentifiedReportMessage) -> Self {
+impl Report {
+    pub fn from_row(row: ReportRow, tags: Vec<String>) -> Self {
         Self {
+            id: row.id,
+            active: row.active,
+            status: row.report_status(),
+            timestamp: row.timestamp,
+            reporter: row.reporter,
+            reported: row.reported,
+            handler: row.handler,
+            handle_ts: row.handle_ts,
+            comment: row.comment,
+            description: row.description,
+            tags,
+            server: row.server,
+            claimant: row.claimant,
+            claim_expires: row.claim_expires,
+            outcome: row.outcome.and_then(|outcome| outcome.parse().ok()),
+            parent: row.parent_id,
+            duplicates: row.duplicates,
+            priority: row.priority,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Report {
+    ///
+    /// An open report of `reporter{id}` against `reported`, for tests.
+    ///
+    pub fn fixture(id: i64) -> Self {
+        Self {
+            id,
+            active: true,
+            status: ReportStatus::Open,
+            timestamp: now(),
+            reporter: format!("reporter{}", id),
+            reported: "reported".to_owned(),
+            handler: None,
+            handle_ts: None,
+            comment: None,
+            description: String::new(),
+            tags: Vec::new(),
+            server: None,
+            claimant: None,
+            claim_expires: None,
+            outcome: None,
+            parent: None,
+            duplicates: 0,
+            priority: 0.0,
+        }
+    }
+}
+
+///
+/// Split comma-joined legacy tags, dropping empty and duplicate ones.
+///
+pub fn split_tags(tags: &str) -> Vec<String> {
+    normalize_tags(tags.split(',').map(|tag| tag.to_owned()).collect())
+}
+
+///
+/// Trim tags, dropping empty and duplicate ones.
+///
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+
+    for tag in tags {
+        let tag = tag.trim();
+
+        if !tag.is_empty() && !normalized.iter().any(|x| x == tag) {
+            normalized.push(tag.to_owned());
+        }
+    }
+
+    normalized
+}
+
+///
+/// Similarity of two descriptions from 0 to 1, the share of distinct words
+/// they have in common regardless of case.
+///
+pub fn description_similarity(a: &str, b: &str) -> f64 {
+    let words = |s: &str| -> HashSet<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect()
+    };
+
+    let (a, b) = (words(a), words(b));
+
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+///
+/// Current time, truncated to the millisecond precision of the API.
+///
+pub fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(3)
+}
+
+///
+/// Convert epoch seconds of the legacy API to a timestamp.
+///
+pub fn from_epoch_seconds(seconds: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+///
+/// Convert a `google.protobuf.Timestamp` to a timestamp with millisecond precision.
+///
+pub fn from_proto_timestamp(ts: &prost_types::Timestamp) -> Option<DateTime<Utc>> {
+    if ts.nanos < 0 || ts.nanos >= 1_000_000_000 {
+        return None;
+    }
+
+    let nanos = (ts.nanos as u32 / 1_000_000) * 1_000_000;
+
+    Utc.timestamp_opt(ts.seconds, nanos).single()
+}
+
+///
+/// Convert a timestamp to a `google.protobuf.Timestamp` with millisecond precision.
+///
+pub fn to_proto_timestamp(ts: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: ts.timestamp(),
+        nanos: (ts.timestamp_subsec_millis() * 1_000_000) as i32,
+    }
+}
+
+impl TryFrom<report::IdentifiedReportMessage> for Report {
+    type Error = &'static str;
+
+    fn try_from(f: report::IdentifiedReportMessage) -> Result<Self, Self::Error> {
+        Ok(Self {
             id: f.id,
             active: f.active,
-            timestamp: f.timestamp,
+            status: ReportStatus::legacy(f.active),
+            timestamp: from_epoch_seconds(f.timestamp).ok_or("invalid timestamp")?,
             reporter: f.reporter,
             reported: f.reported,
             handler: {
@@ -35,8 +477,8 @@ impl From<report::IdentifiedReportMessage> for Report {
                 }
             },
             handle_ts: {
-                if !f.handle_ts == -1 {
-                    Some(f.handle_ts)
+                if f.handle_ts != -1 {
+                    Some(from_epoch_seconds(f.handle_ts).ok_or("invalid handle timestamp")?)
                 } else {
                     None
                 }
@@ -49,14 +491,33 @@ impl From<report::IdentifiedReportMessage> for Report {
                 }
             },
             description: f.desc,
-            tags: {
-                if !f.tags.is_empty() {
-                    Some(f.tags)
+            tags: split_tags(&f.tags),
+            server: {
+                if !f.server.is_empty() {
+                    Some(f.server)
                 } else {
                     None
                 }
             },
-        }
+            claimant: None,
+            claim_expires: None,
+            outcome: {
+                if !f.outcome.is_empty() {
+                    Some(f.outcome.parse()?)
+                } else {
+                    None
+                }
+            },
+            parent: {
+                if f.parent != 0 {
+                    Some(f.parent)
+                } else {
+                    None
+                }
+            },
+            duplicates: f.duplicates,
+            priority: f.priority,
+        })
     }
 }
 
@@ -65,27 +526,514 @@ impl From<Report> for report::IdentifiedReportMessage {
         Self {
             id: f.id,
             active: f.active,
-            timestamp: f.timestamp,
+            timestamp: f.timestamp.timestamp(),
             reporter: f.reporter,
             reported: f.reported,
             handler: f.handler.unwrap_or_else(|| "".to_owned()),
-            handle_ts: f.handle_ts.unwrap_or(-1),
+            handle_ts: f.handle_ts.map(|ts| ts.timestamp()).unwrap_or(-1),
             comment: f.comment.unwrap_or_else(|| "".to_owned()),
             desc: f.description,
-            tags: f.tags.unwrap_or_else(|| "".to_owned()),
+            tags: f.tags.join(","),
+            server: f.server.unwrap_or_else(|| "".to_owned()),
+            notes: Vec::new(),
+            outcome: f
+                .outcome
+                .map(|outcome| outcome.as_str().to_owned())
+                .unwrap_or_default(),
+            parent: f.parent.unwrap_or_default(),
+            duplicates: f.duplicates,
+            priority: f.priority,
         }
     }
 }
 
+impl From<Report> for report1_0::IdentifiedReport {
+    fn from(f: Report) -> Self {
+        Self {
+            id: f.id,
+            active: f.active,
+            status: report1_0::ReportStatus::from(f.status) as i32,
+            claimant: f.claimant.unwrap_or_default(),
+            claim_expires: f.claim_expires.map(to_proto_timestamp),
+            outcome: f
+                .outcome
+                .map(|outcome| report1_0::ResolutionOutcome::from(outcome) as i32)
+                .unwrap_or_default(),
+            parent_id: f.parent.unwrap_or_default(),
+            duplicates: f.duplicates,
+            priority: f.priority,
+            insert_timestamp: Some(to_proto_timestamp(f.timestamp)),
+            handler: f.handler.unwrap_or_else(|| "".to_owned()),
+            handle_timestamp: f.handle_ts.map(to_proto_timestamp),
+            comment: f.comment.unwrap_or_else(|| "".to_owned()),
+            report: Some(report1_0::Report {
+                reporter: f.reporter,
+                reported: f.reported,
+                server: Some(match f.server {
+                    Some(identifier) => {
+                        report1_0::report::Server::Node(report1_0::ServerNode { identifier })
+                    }
+                    None => report1_0::report::Server::Constant(
+                        report1_0::ServerNodeConstant::Undefined as i32,
+                    ),
+                }),
+                description: f.description,
+                tags: Some(f.tags.into()),
+            }),
+        }
+    }
+}
+
+impl From<Vec<String>> for report1_0::Tags {
+    fn from(f: Vec<String>) -> Self {
+        Self {
+            tags: f.into_iter().map(|tag| report1_0::Tag { tag }).collect(),
+        }
+    }
+}
+
+impl From<report1_0::Tags> for Vec<String> {
+    fn from(f: report1_0::Tags) -> Self {
+        normalize_tags(f.tags.into_iter().map(|tag| tag.tag).collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewReport {
+    pub status: ReportStatus,
+    pub timestamp: DateTime<Utc>,
+    pub reporter: String,
+    pub reported: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub server: Option<String>,
+}
+
+impl NewReport {
+    ///
+    /// Split into the `reports` row and the tags stored in `report_tags`.
+    ///
+    pub fn into_parts(self) -> (NewReportRow, Vec<String>) {
+        let row = NewReportRow {
+            status: self.status.as_str().to_owned(),
+            timestamp: self.timestamp,
+            reporter: self.reporter,
+            reported: self.reported,
+            description: self.description,
+            server: self.server,
+            outcome: None,
+            parent_id: None,
+        };
+
+        (row, self.tags)
+    }
+}
+
+///
+/// Insertable part of a `NewReport` stored in the `reports` table.
+///
 #[derive(Debug, Clone, Insertable)]
 #[table_name = "reports"]
-pub struct NewReport {
-    pub active: bool,
-    pub timestamp: i64,
+pub struct NewReportRow {
+    pub timestamp: DateTime<Utc>,
     pub reporter: String,
     pub reported: String,
     pub description: String,
-    pub tags: Option<String>,
+    pub server: Option<String>,
+    pub status: String,
+    pub outcome: Option<String>,
+    pub parent_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "report_tags"]
+pub struct NewReportTag {
+    pub report_id: i64,
+    pub tag: String,
+}
+
+///
+/// Kind of a broadcast waiting in the outbox.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxKind {
+    Insert,
+    Deactivate,
+    Reactivate,
+    /// Move between active statuses, which transporters are not told of.
+    Update,
+    /// Note added to a report, which transporters are not told of either.
+    Note,
+    /// Report merged into another as a duplicate. Transporters are told
+    /// of merged active reports as deactivated ones instead.
+    Merge,
+    /// Priority of a report recomputed, which neither transporters nor
+    /// subscribers are told of.
+    Rescore,
+}
+
+impl OutboxKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxKind::Insert => "insert",
+            OutboxKind::Deactivate => "deactivate",
+            OutboxKind::Reactivate => "reactivate",
+            OutboxKind::Update => "update",
+            OutboxKind::Note => "note",
+            OutboxKind::Merge => "merge",
+            OutboxKind::Rescore => "rescore",
+        }
+    }
+}
+
+impl FromStr for OutboxKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(OutboxKind::Insert),
+            "deactivate" => Ok(OutboxKind::Deactivate),
+            "reactivate" => Ok(OutboxKind::Reactivate),
+            "update" => Ok(OutboxKind::Update),
+            "note" => Ok(OutboxKind::Note),
+            "merge" => Ok(OutboxKind::Merge),
+            "rescore" => Ok(OutboxKind::Rescore),
+            _ => Err("invalid outbox kind"),
+        }
+    }
+}
+
+///
+/// A row of the `report_outbox` table.
+///
+#[derive(Queryable, QueryableByName, Debug, Clone, PartialEq)]
+#[table_name = "report_outbox"]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub report_id: i64,
+    pub kind: String,
+    /// Encoded `report::IdentifiedReportMessage`.
+    pub payload: Vec<u8>,
+    pub attempts: i32,
+    pub next_attempt: DateTime<Utc>,
+    pub delivered_to: Vec<String>,
+    pub last_error: Option<String>,
+    pub created: DateTime<Utc>,
+    /// When the event ran out of attempts, left as a dead letter.
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "report_outbox"]
+pub struct NewOutboxEvent {
+    pub report_id: i64,
+    pub kind: String,
+    pub payload: Vec<u8>,
+}
+
+impl NewOutboxEvent {
+    pub fn new(kind: OutboxKind, report: &Report) -> Self {
+        Self {
+            report_id: report.id,
+            kind: kind.as_str().to_owned(),
+            payload: prost::Message::encode_to_vec(&report::IdentifiedReportMessage::from(
+                report.clone(),
+            )),
+        }
+    }
+}
+
+///
+/// Kind of a state change in the history of a report.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    Insert,
+    Deactivate,
+    Reopen,
+    Comment,
+    Assign,
+    /// Move between active statuses.
+    Status,
+    Merge,
+}
+
+impl HistoryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryKind::Insert => "insert",
+            HistoryKind::Deactivate => "deactivate",
+            HistoryKind::Reopen => "reopen",
+            HistoryKind::Comment => "comment",
+            HistoryKind::Assign => "assign",
+            HistoryKind::Status => "status",
+            HistoryKind::Merge => "merge",
+        }
+    }
+}
+
+impl FromStr for HistoryKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(HistoryKind::Insert),
+            "deactivate" => Ok(HistoryKind::Deactivate),
+            "reopen" => Ok(HistoryKind::Reopen),
+            "comment" => Ok(HistoryKind::Comment),
+            "assign" => Ok(HistoryKind::Assign),
+            "status" => Ok(HistoryKind::Status),
+            "merge" => Ok(HistoryKind::Merge),
+            _ => Err("invalid history kind"),
+        }
+    }
+}
+
+///
+/// A row of the `report_events` table.
+///
+#[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct HistoryEvent {
+    pub id: i64,
+    pub report_id: i64,
+    pub kind: String,
+    pub actor: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    /// Changed fields before the change, by name.
+    pub before: Option<Value>,
+    /// Changed fields after the change, by name.
+    pub after: Option<Value>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "report_events"]
+pub struct NewHistoryEvent {
+    pub report_id: i64,
+    pub kind: String,
+    pub actor: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub reason: Option<String>,
+}
+
+impl NewHistoryEvent {
+    ///
+    /// Insert of a report by its reporter.
+    ///
+    pub fn insert(report: &Report) -> Self {
+        Self {
+            report_id: report.id,
+            kind: HistoryKind::Insert.as_str().to_owned(),
+            actor: Some(report.reporter.clone()),
+            timestamp: report.timestamp,
+            before: None,
+            after: Some(json!({
+                "reporter": report.reporter,
+                "reported": report.reported,
+                "description": report.description,
+                "tags": report.tags,
+                "server": report.server,
+                "active": report.active,
+                "status": report.status.as_str(),
+            })),
+            reason: None,
+        }
+    }
+
+    ///
+    /// Change of the given fields of a report, `None` when none of them
+    /// changed.
+    ///
+    pub fn change(
+        kind: HistoryKind,
+        actor: Option<String>,
+        timestamp: DateTime<Utc>,
+        before: &ReportRow,
+        after: &ReportRow,
+        fields: &[&str],
+    ) -> Option<Self> {
+        let (before_state, after_state) = (state(before), state(after));
+        let mut changed_before = Map::new();
+        let mut changed_after = Map::new();
+
+        for field in fields {
+            let (old, new) = (&before_state[*field], &after_state[*field]);
+
+            if old != new {
+                changed_before.insert(field.to_string(), old.clone());
+                changed_after.insert(field.to_string(), new.clone());
+            }
+        }
+
+        if changed_after.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            report_id: after.id,
+            kind: kind.as_str().to_owned(),
+            actor,
+            timestamp,
+            before: Some(Value::Object(changed_before)),
+            after: Some(Value::Object(changed_after)),
+            reason: None,
+        })
+    }
+
+    pub fn with_reason(mut self, reason: Option<String>) -> Self {
+        self.reason = reason;
+        self
+    }
+}
+
+///
+/// Mutable fields of a report, by name.
+///
+fn state(row: &ReportRow) -> Value {
+    json!({
+        "status": row.status,
+        "active": row.active,
+        "claimant": row.claimant,
+        "outcome": row.outcome,
+        "handler": row.handler,
+        "handle_ts": row.handle_ts.map(|ts| ts.to_rfc3339()),
+        "comment": row.comment,
+        "parent_id": row.parent_id,
+        "duplicates": row.duplicates,
+    })
+}
+
+impl From<HistoryEvent> for report1_0::ReportHistoryEvent {
+    fn from(f: HistoryEvent) -> Self {
+        let kind = match f.kind.parse::<HistoryKind>() {
+            Ok(HistoryKind::Insert) => report1_0::ReportHistoryKind::Insert,
+            Ok(HistoryKind::Deactivate) => report1_0::ReportHistoryKind::Deactivate,
+            Ok(HistoryKind::Reopen) => report1_0::ReportHistoryKind::Reopen,
+            Ok(HistoryKind::Comment) => report1_0::ReportHistoryKind::Comment,
+            Ok(HistoryKind::Assign) => report1_0::ReportHistoryKind::Assign,
+            Ok(HistoryKind::Status) => report1_0::ReportHistoryKind::Status,
+            Ok(HistoryKind::Merge) => report1_0::ReportHistoryKind::Merge,
+            Err(_) => report1_0::ReportHistoryKind::Unknown,
+        };
+
+        Self {
+            id: f.id,
+            kind: kind as i32,
+            actor: f.actor.unwrap_or_default(),
+            timestamp: Some(to_proto_timestamp(f.timestamp)),
+            before: f.before.map(|val| val.to_string()).unwrap_or_default(),
+            after: f.after.map(|val| val.to_string()).unwrap_or_default(),
+            reason: f.reason.unwrap_or_default(),
+        }
+    }
+}
+
+///
+/// A row of the `report_notes` table, a note left by a moderator on a report.
+///
+#[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct ReportNote {
+    pub id: i64,
+    pub report_id: i64,
+    pub author: String,
+    pub body: String,
+    pub created: DateTime<Utc>,
+    /// Time of the last edit, `None` when never edited.
+    pub edited: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "report_notes"]
+pub struct NewReportNote {
+    pub report_id: i64,
+    pub author: String,
+    pub body: String,
+    pub created: DateTime<Utc>,
+}
+
+///
+/// A row of the `report_note_edits` table, the body of a note before an edit.
+///
+#[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct NoteEdit {
+    pub id: i64,
+    pub note_id: i64,
+    pub editor: String,
+    pub timestamp: DateTime<Utc>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "report_note_edits"]
+pub struct NewNoteEdit {
+    pub note_id: i64,
+    pub editor: String,
+    pub timestamp: DateTime<Utc>,
+    pub body: String,
+}
+
+impl From<ReportNote> for report1_0::ReportNote {
+    fn from(f: ReportNote) -> Self {
+        Self {
+            id: f.id,
+            report_id: f.report_id,
+            author: f.author,
+            body: f.body,
+            created: Some(to_proto_timestamp(f.created)),
+            edited: f.edited.map(to_proto_timestamp),
+            edits: Vec::new(),
+        }
+    }
+}
+
+impl From<NoteEdit> for report1_0::ReportNoteEdit {
+    fn from(f: NoteEdit) -> Self {
+        Self {
+            editor: f.editor,
+            timestamp: Some(to_proto_timestamp(f.timestamp)),
+            body: f.body,
+        }
+    }
+}
+
+impl From<ReportNote> for report::ReportNoteMessage {
+    fn from(f: ReportNote) -> Self {
+        Self {
+            id: f.id,
+            author: f.author,
+            body: f.body,
+            created: f.created.timestamp(),
+            edited: f.edited.map(|ts| ts.timestamp()).unwrap_or(-1),
+        }
+    }
+}
+
+///
+/// A report transporter registered by a game server, broadcast to until
+/// its lease expires.
+///
+#[derive(Queryable, Insertable, Debug, Clone, PartialEq)]
+#[table_name = "transporters"]
+pub struct TransporterRegistration {
+    pub address: String,
+    pub node: String,
+    pub registered: DateTime<Utc>,
+    pub heartbeat: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl From<TransporterRegistration> for report1_0::TransporterRegistration {
+    fn from(f: TransporterRegistration) -> Self {
+        Self {
+            address: f.address,
+            node: Some(report1_0::ServerNode { identifier: f.node }),
+            registered: Some(to_proto_timestamp(f.registered)),
+            heartbeat: Some(to_proto_timestamp(f.heartbeat)),
+            expires: Some(to_proto_timestamp(f.expires)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -93,7 +1041,10 @@ pub struct ReportRequest {
     pub reporter: String,
     pub reported: String,
     pub desc: String,
-    pub tags: String,
+    pub tags: Vec<String>,
+
+    /// Identifier of the server node the report originates from.
+    pub server: Option<String>,
 }
 
 impl From<report::ReportMessage> for ReportRequest {
@@ -102,22 +1053,131 @@ impl From<report::ReportMessage> for ReportRequest {
             reporter: f.reporter,
             reported: f.reported,
             desc: f.desc,
-            tags: f.tags,
+            tags: split_tags(&f.tags),
+            server: {
+                if !f.server.is_empty() {
+                    Some(f.server)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl From<report1_0::Report> for ReportRequest {
+    fn from(f: report1_0::Report) -> Self {
+        Self {
+            reporter: f.reporter,
+            reported: f.reported,
+            desc: f.description,
+            tags: f.tags.map(|tags| tags.into()).unwrap_or_default(),
+            server: match f.server {
+                Some(report1_0::report::Server::Node(node)) => Some(node.identifier),
+                _ => None,
+            },
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct ReportDeactivateRequest {
+pub struct ReportReactivateRequest {
     pub id: i64,
     pub operator: String,
+    pub reason: Option<String>,
+    /// Keep the handler, handle time and comment of the prior handling.
+    pub keep_handling: bool,
+}
+
+impl From<report1_0::ReportReactivateRequest> for ReportReactivateRequest {
+    fn from(f: report1_0::ReportReactivateRequest) -> Self {
+        Self {
+            id: f.id,
+            operator: f.operator,
+            reason: {
+                if !f.reason.is_empty() {
+                    Some(f.reason)
+                } else {
+                    None
+                }
+            },
+            keep_handling: f.keep_handling,
+        }
+    }
+}
+
+///
+/// Move of a report to another status.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportTransition {
+    pub id: i64,
+    pub status: ReportStatus,
+    pub operator: String,
+    /// Comment left when closing the report, `None` keeping the current one.
     pub comment: Option<String>,
+    /// Outcome of handling the report, required when closing it.
+    pub outcome: Option<ResolutionOutcome>,
+    pub reason: Option<String>,
+    /// Keep the handler, handle time and comment of the prior handling
+    /// when reopening the report.
+    pub keep_handling: bool,
+    /// Move the report even though another handler claimed it.
+    pub force: bool,
+}
+
+impl From<ReportDeactivateRequest> for ReportTransition {
+    fn from(f: ReportDeactivateRequest) -> Self {
+        Self {
+            id: f.id,
+            status: ReportStatus::Resolved,
+            operator: f.operator,
+            comment: f.comment,
+            outcome: f.outcome,
+            reason: None,
+            keep_handling: false,
+            force: f.force,
+        }
+    }
 }
 
-impl From<report::ReportDeactivateRequest> for ReportDeactivateRequest {
-    fn from(f: report::ReportDeactivateRequest) -> Self {
+impl From<ReportReactivateRequest> for ReportTransition {
+    fn from(f: ReportReactivateRequest) -> Self {
         Self {
             id: f.id,
+            status: ReportStatus::Open,
+            operator: f.operator,
+            comment: None,
+            outcome: None,
+            reason: f.reason,
+            keep_handling: f.keep_handling,
+            force: false,
+        }
+    }
+}
+
+impl From<ReportReleaseRequest> for ReportTransition {
+    fn from(f: ReportReleaseRequest) -> Self {
+        Self {
+            id: f.id,
+            status: ReportStatus::Open,
+            operator: f.operator,
+            comment: None,
+            outcome: None,
+            reason: f.reason,
+            keep_handling: false,
+            force: f.force,
+        }
+    }
+}
+
+impl TryFrom<report1_0::ReportStatusRequest> for ReportTransition {
+    type Error = &'static str;
+
+    fn try_from(f: report1_0::ReportStatusRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: f.id,
+            status: from_proto_status(f.status)?,
             operator: f.operator,
             comment: {
                 if !f.comment.is_empty() {
@@ -126,14 +1186,195 @@ impl From<report::ReportDeactivateRequest> for ReportDeactivateRequest {
                     None
                 }
             },
+            outcome: from_proto_outcome(f.outcome)?,
+            reason: {
+                if !f.reason.is_empty() {
+                    Some(f.reason)
+                } else {
+                    None
+                }
+            },
+            keep_handling: f.keep_handling,
+            force: f.force,
+        })
+    }
+}
+
+///
+/// Outcome of a `ReportTransition`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransitionOutcome {
+    /// The report moved, broadcast as the given kind of change.
+    Moved(Box<Report>, OutboxKind),
+    /// The report is in a status it may not move to the requested one from.
+    Illegal(ReportStatus),
+    /// The report is claimed by the given handler.
+    Held(String),
+    /// No report has the given id.
+    NotFound,
+}
+
+///
+/// A report merged into another as a duplicate.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedReport {
+    pub report: Report,
+    /// Report merged into, with its duplicates counted.
+    pub parent: Report,
+}
+
+///
+/// Outcome of inserting a report.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertOutcome {
+    Inserted(Box<Report>),
+    /// The report was inserted as a duplicate of an active report.
+    Duplicate(Box<MergedReport>),
+}
+
+///
+/// Merge of a report into another as a duplicate.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportMerge {
+    pub id: i64,
+    /// Report to merge into, or the one it is a duplicate of.
+    pub into: i64,
+    pub operator: String,
+    pub reason: Option<String>,
+    /// Merge the report even though another handler claimed it.
+    pub force: bool,
+}
+
+impl From<report1_0::ReportMergeRequest> for ReportMerge {
+    fn from(f: report1_0::ReportMergeRequest) -> Self {
+        Self {
+            id: f.id,
+            into: f.into,
+            operator: f.operator,
+            reason: {
+                if !f.reason.is_empty() {
+                    Some(f.reason)
+                } else {
+                    None
+                }
+            },
+            force: f.force,
+        }
+    }
+}
+
+///
+/// Outcome of a `ReportMerge`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeOutcome {
+    Merged(Box<MergedReport>),
+    /// One of the reports does not exist.
+    NotFound,
+    /// The report would be merged into itself or one of its duplicates.
+    IntoItself,
+    /// The report is in a status it may not become a duplicate from.
+    Illegal(ReportStatus),
+    /// The report is claimed by the given handler.
+    Held(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportReleaseRequest {
+    pub id: i64,
+    pub operator: String,
+    pub reason: Option<String>,
+    /// Release the claim of another handler.
+    pub force: bool,
+}
+
+impl From<report1_0::ReportReleaseRequest> for ReportReleaseRequest {
+    fn from(f: report1_0::ReportReleaseRequest) -> Self {
+        Self {
+            id: f.id,
+            operator: f.handler,
+            reason: {
+                if !f.reason.is_empty() {
+                    Some(f.reason)
+                } else {
+                    None
+                }
+            },
+            force: f.force,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportDeactivateRequest {
+    pub id: i64,
+    pub operator: String,
+    pub comment: Option<String>,
+    pub outcome: Option<ResolutionOutcome>,
+    /// Deactivate the report even though another handler claimed it.
+    pub force: bool,
+}
+
+impl TryFrom<report::ReportDeactivateRequest> for ReportDeactivateRequest {
+    type Error = &'static str;
+
+    fn try_from(f: report::ReportDeactivateRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: f.id,
+            operator: f.operator,
+            comment: {
+                if !f.comment.is_empty() {
+                    Some(f.comment)
+                } else {
+                    None
+                }
+            },
+            // Older clients do not know of outcomes.
+            outcome: {
+                if !f.outcome.is_empty() {
+                    Some(f.outcome.parse()?)
+                } else {
+                    Some(ResolutionOutcome::Other)
+                }
+            },
+            force: false,
+        })
+    }
+}
+
+impl TryFrom<report1_0::ReportDeactivateRequest> for ReportDeactivateRequest {
+    type Error = &'static str;
+
+    fn try_from(f: report1_0::ReportDeactivateRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: f.id,
+            operator: f.handler,
+            comment: {
+                if !f.comment.is_empty() {
+                    Some(f.comment)
+                } else {
+                    None
+                }
+            },
+            outcome: from_proto_outcome(f.outcome)?,
+            force: f.force,
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReportQuery {
     pub query: String,
     pub id: i64,
+    /// Return the notes of reports along with them.
+    pub include_notes: bool,
+
+    pub page_size: i64,
+    pub page_token: String,
 }
 
 impl From<report::ReportQuery> for ReportQuery {
@@ -141,6 +1382,48 @@ impl From<report::ReportQuery> for ReportQuery {
         Self {
             query: f.query,
             id: f.id,
+            include_notes: f.include_notes,
+            page_size: f.page_size,
+            page_token: f.page_token,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUSES: [ReportStatus; 6] = [
+        ReportStatus::Open,
+        ReportStatus::Claimed,
+        ReportStatus::InReview,
+        ReportStatus::Resolved,
+        ReportStatus::Rejected,
+        ReportStatus::Duplicate,
+    ];
+
+    #[test]
+    fn transition_table() {
+        // Rows move from, columns move to, in the order of `STATUSES`.
+        let allowed = [
+            "010111", // open
+            "101111", // claimed
+            "110111", // in review
+            "100000", // resolved
+            "100000", // rejected
+            "100000", // duplicate
+        ];
+
+        for (from, row) in STATUSES.iter().zip(allowed.iter()) {
+            for (to, allowed) in STATUSES.iter().zip(row.chars()) {
+                assert_eq!(
+                    from.can_transition_to(*to),
+                    allowed == '1',
+                    "{} -> {}",
+                    from,
+                    to
+                );
+            }
         }
     }
 }
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::str::FromStr;
+
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+use uuid::Uuid;
+
+use crate::models::OutboxKind;
+
+///
+/// Postgres channel report changes are notified on.
+///
+pub const CHANGE_CHANNEL: &str = "report_changes";
+
+///
+/// Payload of a notification on `CHANGE_CHANNEL`, formatted as
+/// `"{instance} {kind} {id}"`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeNotification {
+    /// Instance that made the change.
+    pub instance: Uuid,
+    pub kind: OutboxKind,
+    pub id: i64,
+}
+
+impl fmt::Display for ChangeNotification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.instance, self.kind.as_str(), self.id)
+    }
+}
+
+impl FromStr for ChangeNotification {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(' ');
+
+        let instance = match parts.next().map(Uuid::parse_str) {
+            Some(Ok(val)) => val,
+            _ => return Err("invalid change instance"),
+        };
+
+        let kind = parts.next().ok_or("missing change kind")?.parse()?;
+
+        let id = match parts.next().map(str::parse) {
+            Some(Ok(val)) => val,
+            _ => return Err("invalid change id"),
+        };
+
+        Ok(Self { instance, kind, id })
+    }
+}
+
+///
+/// Notify every listening instance of a change, once the surrounding
+/// transaction commits.
+///
+pub fn notify(conn: &PgConnection, change: &ChangeNotification) -> QueryResult<()> {
+    diesel::sql_query("SELECT pg_notify($1, $2)")
+        .bind::<Text, _>(CHANGE_CHANNEL)
+        .bind::<Text, _>(change.to_string())
+        .execute(conn)?;
+
+    Ok(())
+}
+
+///
+/// A dedicated connection listening on `CHANGE_CHANNEL`.
+///
+pub struct ChangeListener {
+    // Listening stops once the client is dropped.
+    _client: tokio_postgres::Client,
+    notifications: mpsc::UnboundedReceiver<String>,
+}
+
+impl ChangeListener {
+    pub async fn connect(addr: &str) -> Result<Self, tokio_postgres::Error> {
+        let (client, mut connection) = tokio_postgres::connect(addr, NoTls).await?;
+        let (tx, notifications) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+
+            while let Some(Ok(message)) = messages.next().await {
+                if let AsyncMessage::Notification(notification) = message {
+                    if tx.send(notification.payload().to_owned()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        client
+            .batch_execute(&format!("LISTEN {}", CHANGE_CHANNEL))
+            .await?;
+
+        Ok(Self {
+            _client: client,
+            notifications,
+        })
+    }
+
+    ///
+    /// Next change notified, `None` once the connection is lost.
+    ///
+    /// Unparseable payloads are skipped.
+    ///
+    pub async fn recv(&mut self) -> Option<ChangeNotification> {
+        loop {
+            let payload = self.notifications.recv().await?;
+
+            if let Ok(change) = payload.parse() {
+                return Some(change);
+            }
+        }
+    }
+}
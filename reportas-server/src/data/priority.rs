@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Text, Timestamptz};
+
+use crate::models::Report;
+
+///
+/// Weights of the inputs of the priority of a report.
+///
+/// Priorities grow with the distinct players reporting the same player
+/// within `window`, the weights of the tags, the accuracy of the reporter
+/// and the whole hours waited while active.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriorityWeights {
+    pub window: Duration,
+    /// Each distinct reporter besides the first.
+    pub reporters: f64,
+    /// Reporters whose reports were all upheld, taken away for reporters
+    /// whose reports were all dismissed.
+    pub accuracy: f64,
+    pub waiting_per_hour: f64,
+    pub max_waiting_hours: i64,
+    pub tags: HashMap<String, f64>,
+}
+
+impl PriorityWeights {
+    ///
+    /// Priority of a report at `at`, rounded to hundredths.
+    ///
+    /// `reporters` counts the distinct players reporting the reported
+    /// player within the window.
+    ///
+    pub fn score(
+        &self,
+        report: &Report,
+        reporters: i64,
+        record: &ReporterRecord,
+        at: DateTime<Utc>,
+    ) -> f64 {
+        let waited = (at - report.timestamp)
+            .num_hours()
+            .clamp(0, self.max_waiting_hours);
+
+        let tags: f64 = report
+            .tags
+            .iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .sum();
+
+        let score = self.reporters * (reporters - 1).max(0) as f64
+            + tags
+            + self.accuracy * (2.0 * record.accuracy() - 1.0)
+            + self.waiting_per_hour * waited as f64;
+
+        (score * 100.0).round() / 100.0
+    }
+}
+
+///
+/// Handled reports of a reporter.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReporterRecord {
+    /// Closed as punished or warned.
+    pub upheld: i64,
+    /// Closed without action or as a false report.
+    pub dismissed: i64,
+}
+
+impl ReporterRecord {
+    ///
+    /// Share of upheld reports, starting out at a half for reporters with
+    /// few handled reports.
+    ///
+    pub fn accuracy(&self) -> f64 {
+        (self.upheld + 1) as f64 / (self.upheld + self.dismissed + 2) as f64
+    }
+}
+
+#[derive(QueryableByName)]
+struct ReporterCount {
+    #[sql_type = "Text"]
+    reported: String,
+    #[sql_type = "BigInt"]
+    reporters: i64,
+}
+
+///
+/// Distinct players reporting each of the given players since `since`.
+///
+pub fn reporter_counts(
+    conn: &PgConnection,
+    reported: Vec<String>,
+    since: DateTime<Utc>,
+) -> QueryResult<HashMap<String, i64>> {
+    let counts = diesel::sql_query(
+        "SELECT reported, COUNT(DISTINCT reporter) AS reporters FROM reports \
+         WHERE reported = ANY($1) AND timestamp >= $2 GROUP BY reported",
+    )
+    .bind::<Array<Text>, _>(reported)
+    .bind::<Timestamptz, _>(since)
+    .load::<ReporterCount>(conn)?;
+
+    Ok(counts
+        .into_iter()
+        .map(|count| (count.reported, count.reporters))
+        .collect())
+}
+
+#[derive(QueryableByName)]
+struct ReporterRecordRow {
+    #[sql_type = "Text"]
+    reporter: String,
+    #[sql_type = "BigInt"]
+    upheld: i64,
+    #[sql_type = "BigInt"]
+    dismissed: i64,
+}
+
+///
+/// Handled reports of each of the given reporters.
+///
+pub fn reporter_records(
+    conn: &PgConnection,
+    reporters: Vec<String>,
+) -> QueryResult<HashMap<String, ReporterRecord>> {
+    let records = diesel::sql_query(
+        "SELECT reporter, \
+         COUNT(*) FILTER (WHERE outcome IN ('punished', 'warned')) AS upheld, \
+         COUNT(*) FILTER (WHERE outcome IN ('no_action', 'false_report')) AS dismissed \
+         FROM reports WHERE reporter = ANY($1) GROUP BY reporter",
+    )
+    .bind::<Array<Text>, _>(reporters)
+    .load::<ReporterRecordRow>(conn)?;
+
+    Ok(records
+        .into_iter()
+        .map(|row| {
+            let record = ReporterRecord {
+                upheld: row.upheld,
+                dismissed: row.dismissed,
+            };
+
+            (row.reporter, record)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::now;
+
+    fn weights() -> PriorityWeights {
+        PriorityWeights {
+            window: Duration::hours(24),
+            reporters: 10.0,
+            accuracy: 20.0,
+            waiting_per_hour: 1.0,
+            max_waiting_hours: 48,
+            tags: vec![("cheating".to_owned(), 15.0), ("spam".to_owned(), -2.0)]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn report(tags: &[&str], timestamp: DateTime<Utc>) -> Report {
+        Report {
+            timestamp,
+            tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+            ..Report::fixture(1)
+        }
+    }
+
+    #[test]
+    fn first_reporter_adds_nothing() {
+        let ts = now();
+        let report = report(&[], ts);
+        let record = ReporterRecord::default();
+
+        assert_eq!(weights().score(&report, 0, &record, ts), 0.0);
+        assert_eq!(weights().score(&report, 1, &record, ts), 0.0);
+        assert_eq!(weights().score(&report, 2, &record, ts), 10.0);
+        assert_eq!(weights().score(&report, 4, &record, ts), 30.0);
+    }
+
+    #[test]
+    fn waiting_is_clamped() {
+        let ts = now();
+        let record = ReporterRecord::default();
+
+        let waited =
+            |hours: i64| weights().score(&report(&[], ts - Duration::hours(hours)), 1, &record, ts);
+
+        assert_eq!(waited(0), 0.0);
+        assert_eq!(waited(5), 5.0);
+        assert_eq!(waited(48), 48.0);
+        assert_eq!(waited(500), 48.0);
+        // Reports from the future of another instance never wait negatively.
+        assert_eq!(waited(-5), 0.0);
+    }
+
+    #[test]
+    fn tags_add_their_weights() {
+        let ts = now();
+        let record = ReporterRecord::default();
+
+        let tagged = |tags: &[&str]| weights().score(&report(tags, ts), 1, &record, ts);
+
+        assert_eq!(tagged(&["cheating"]), 15.0);
+        assert_eq!(tagged(&["cheating", "spam"]), 13.0);
+        assert_eq!(tagged(&["unweighted"]), 0.0);
+    }
+
+    #[test]
+    fn score_is_rounded_to_hundredths() {
+        let ts = now();
+        let report = report(&[], ts);
+        let record = ReporterRecord {
+            upheld: 1,
+            dismissed: 0,
+        };
+
+        // 20 * (2 * 2/3 - 1)
+        assert_eq!(weights().score(&report, 1, &record, ts), 6.67);
+    }
+
+    #[test]
+    fn accuracy_starts_out_neutral() {
+        assert_eq!(ReporterRecord::default().accuracy(), 0.5);
+
+        let upheld = ReporterRecord {
+            upheld: 3,
+            dismissed: 0,
+        };
+        let dismissed = ReporterRecord {
+            upheld: 0,
+            dismissed: 3,
+        };
+
+        assert_eq!(upheld.accuracy(), 0.8);
+        assert_eq!(dismissed.accuracy(), 0.2);
+    }
+}
@@ -0,0 +1,784 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+
+use crate::models::{Report, ReportStatus, ResolutionOutcome};
+use crate::schema::{report_tags, reports};
+use crate::search;
+
+///
+/// A single predicate a report must satisfy.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportFilter {
+    Id(i64),
+    Reported(String),
+    Reporter(String),
+    Active(bool),
+    /// In any of the given statuses.
+    Status(Vec<ReportStatus>),
+    /// Closed with any of the given outcomes.
+    Outcome(Vec<ResolutionOutcome>),
+    /// Handled by the given handler, or by nobody on `None`.
+    Handler(Option<String>),
+    /// Inserted at or before the given timestamp.
+    InsertTimestamp(DateTime<Utc>),
+    /// Handled at or before the given timestamp.
+    HandleTimestamp(DateTime<Utc>),
+    /// Originating from the given server node, or from no specific node on `None`.
+    Server(Option<String>),
+    /// Tagged with at least one of the given tags.
+    TagsAny(Vec<String>),
+    /// Tagged with every one of the given tags.
+    TagsAll(Vec<String>),
+    /// Inserted within the given range.
+    InsertRange(TimeRange),
+    /// Handled within the given range.
+    HandleRange(TimeRange),
+    /// Description or comment matching a web search style query.
+    Search(String),
+    /// Priority of at least the given one.
+    MinPriority(f64),
+}
+
+///
+/// A half-open `[from, to)` range of time.
+///
+/// Missing bounds leave the range unbounded on that side.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    pub fn new(from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        Self { from, to }
+    }
+
+    pub fn contains(&self, ts: DateTime<Utc>) -> bool {
+        let after_from = match self.from {
+            Some(from) => ts >= from,
+            None => true,
+        };
+
+        let before_to = match self.to {
+            Some(to) => ts < to,
+            None => true,
+        };
+
+        after_from && before_to
+    }
+}
+
+impl ReportFilter {
+    pub fn matches(&self, report: &Report) -> bool {
+        match self {
+            ReportFilter::Id(value) => report.id == *value,
+            ReportFilter::Reported(value) => report.reported == *value,
+            ReportFilter::Reporter(value) => report.reporter == *value,
+            ReportFilter::Active(value) => report.active == *value,
+            ReportFilter::Status(values) => values.contains(&report.status),
+            ReportFilter::Outcome(values) => match report.outcome {
+                Some(value) => values.contains(&value),
+                None => false,
+            },
+            ReportFilter::Handler(value) => report.handler == *value,
+            ReportFilter::InsertTimestamp(value) => report.timestamp <= *value,
+            ReportFilter::HandleTimestamp(value) => match report.handle_ts {
+                Some(ts) => ts <= *value,
+                None => false,
+            },
+            ReportFilter::Server(value) => report.server == *value,
+            ReportFilter::TagsAny(values) => values.iter().any(|x| report.tags.contains(x)),
+            ReportFilter::TagsAll(values) => values.iter().all(|x| report.tags.contains(x)),
+            ReportFilter::InsertRange(range) => range.contains(report.timestamp),
+            ReportFilter::HandleRange(range) => match report.handle_ts {
+                Some(ts) => range.contains(ts),
+                None => false,
+            },
+            // Only the database can search, so cached reports never match.
+            ReportFilter::Search(_) => false,
+            ReportFilter::MinPriority(value) => report.priority >= *value,
+        }
+    }
+}
+
+///
+/// A set of `ReportFilter`s combined with AND.
+///
+/// An empty set matches every report.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportFilterSet {
+    pub filters: Vec<ReportFilter>,
+}
+
+impl ReportFilterSet {
+    pub fn new(filters: Vec<ReportFilter>) -> Self {
+        Self { filters }
+    }
+
+    ///
+    /// Filter set matching every report.
+    ///
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Add a filter to the set.
+    ///
+    pub fn with(mut self, filter: ReportFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    pub fn matches(&self, report: &Report) -> bool {
+        self.filters.iter().all(|filter| filter.matches(report))
+    }
+
+    ///
+    /// Web search style query combining every `Search` filter of the set.
+    ///
+    pub fn search(&self) -> Option<String> {
+        let queries: Vec<&str> = self
+            .filters
+            .iter()
+            .filter_map(|filter| match filter {
+                ReportFilter::Search(value) => Some(value.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        if !queries.is_empty() {
+            Some(queries.join(" "))
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Compile the filter set into a query for a single page.
+    ///
+    /// One report more than the page size is loaded to tell whether a
+    /// next page exists.
+    ///
+    pub fn to_page_query<'a>(
+        &self,
+        order: &ReportOrder,
+        page: &Page,
+    ) -> reports::BoxedQuery<'a, Pg> {
+        let mut query = self.to_query();
+
+        if let Some(after) = &page.after {
+            query = order.after(query, after, self.search());
+        }
+
+        order.apply(query, self.search()).limit(page.size + 1)
+    }
+
+    ///
+    /// Compile the filter set into a single query sorted in the given order.
+    ///
+    pub fn to_sorted_query<'a>(&self, order: &ReportOrder) -> reports::BoxedQuery<'a, Pg> {
+        order.apply(self.to_query(), self.search())
+    }
+
+    ///
+    /// Compile the filter set into a single boxed query.
+    ///
+    pub fn to_query<'a>(&self) -> reports::BoxedQuery<'a, Pg> {
+        use crate::schema::reports::dsl::*;
+
+        let mut query = reports.into_boxed();
+
+        for filter in self.filters.iter().cloned() {
+            query = match filter {
+                ReportFilter::Id(value) => query.filter(id.eq(value)),
+                ReportFilter::Reported(value) => query.filter(reported.eq(value)),
+                ReportFilter::Reporter(value) => query.filter(reporter.eq(value)),
+                ReportFilter::Active(value) => query.filter(active.eq(value)),
+                ReportFilter::Status(values) => query
+                    .filter(status.eq_any(values.iter().map(|x| x.as_str()).collect::<Vec<_>>())),
+                ReportFilter::Outcome(values) => query
+                    .filter(outcome.eq_any(values.iter().map(|x| x.as_str()).collect::<Vec<_>>())),
+                ReportFilter::Handler(Some(value)) => query.filter(handler.eq(value)),
+                ReportFilter::Handler(None) => query.filter(handler.is_null()),
+                ReportFilter::InsertTimestamp(value) => query.filter(timestamp.le(value)),
+                ReportFilter::HandleTimestamp(value) => query.filter(handle_ts.le(value)),
+                ReportFilter::Server(Some(value)) => query.filter(server.eq(value)),
+                ReportFilter::Server(None) => query.filter(server.is_null()),
+                ReportFilter::TagsAny(values) => query.filter(
+                    id.eq_any(
+                        report_tags::table
+                            .select(report_tags::report_id)
+                            .filter(report_tags::tag.eq_any(values)),
+                    ),
+                ),
+                ReportFilter::TagsAll(values) => {
+                    for value in values {
+                        query = query.filter(
+                            id.eq_any(
+                                report_tags::table
+                                    .select(report_tags::report_id)
+                                    .filter(report_tags::tag.eq(value)),
+                            ),
+                        );
+                    }
+
+                    query
+                }
+                ReportFilter::InsertRange(range) => {
+                    if let Some(from) = range.from {
+                        query = query.filter(timestamp.ge(from));
+                    }
+
+                    if let Some(to) = range.to {
+                        query = query.filter(timestamp.lt(to));
+                    }
+
+                    query
+                }
+                ReportFilter::HandleRange(range) => {
+                    query = query.filter(handle_ts.is_not_null());
+
+                    if let Some(from) = range.from {
+                        query = query.filter(handle_ts.ge(from));
+                    }
+
+                    if let Some(to) = range.to {
+                        query = query.filter(handle_ts.lt(to));
+                    }
+
+                    query
+                }
+                ReportFilter::Search(value) => query.filter(search::matches(value)),
+                ReportFilter::MinPriority(value) => query.filter(priority.ge(value)),
+            };
+        }
+
+        query
+    }
+}
+
+///
+/// Column reports are sorted by.
+///
+/// Ties are always broken by id.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Id,
+    InsertTimestamp,
+    /// Unhandled reports sort last in either direction.
+    HandleTimestamp,
+    /// Rank against the `Search` filters, by id without any.
+    Relevance,
+    Priority,
+}
+
+///
+/// Value of the sort key of a report.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortValue {
+    Timestamp(DateTime<Utc>),
+    Rank(f32),
+    Priority(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+///
+/// Order of queried reports.
+///
+/// Defaults to ascending insert time.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportOrder {
+    pub key: SortKey,
+    pub direction: SortDirection,
+}
+
+impl Default for ReportOrder {
+    fn default() -> Self {
+        Self {
+            key: SortKey::InsertTimestamp,
+            direction: SortDirection::Ascending,
+        }
+    }
+}
+
+impl ReportOrder {
+    pub fn new(key: SortKey, direction: SortDirection) -> Self {
+        Self { key, direction }
+    }
+
+    ///
+    /// Timestamp the report is sorted by, `None` for unhandled reports
+    /// and keys other than timestamps.
+    ///
+    fn timestamp(&self, report: &Report) -> Option<DateTime<Utc>> {
+        match self.key {
+            SortKey::InsertTimestamp => Some(report.timestamp),
+            SortKey::HandleTimestamp => report.handle_ts,
+            SortKey::Id | SortKey::Relevance | SortKey::Priority => None,
+        }
+    }
+
+    ///
+    /// Compare two reports the same way the database sorts them.
+    ///
+    /// Ranks are not known outside of the database, so relevance
+    /// compares like id.
+    ///
+    pub fn compare(&self, a: &Report, b: &Report) -> Ordering {
+        let ordering = match (self.key, self.timestamp(a), self.timestamp(b)) {
+            (SortKey::Priority, _, _) => a.priority.total_cmp(&b.priority).then(a.id.cmp(&b.id)),
+            (_, Some(x), Some(y)) => x.cmp(&y).then(a.id.cmp(&b.id)),
+            (_, Some(_), None) => return Ordering::Less,
+            (_, None, Some(_)) => return Ordering::Greater,
+            (_, None, None) => a.id.cmp(&b.id),
+        };
+
+        match self.direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        }
+    }
+
+    ///
+    /// Sort a query in this order, ranking against a search query.
+    ///
+    pub fn apply<'a>(
+        &self,
+        query: reports::BoxedQuery<'a, Pg>,
+        search: Option<String>,
+    ) -> reports::BoxedQuery<'a, Pg> {
+        use crate::schema::reports::dsl::*;
+
+        let key = match (self.key, &search) {
+            (SortKey::Relevance, None) => SortKey::Id,
+            (key, _) => key,
+        };
+
+        match (key, self.direction) {
+            (SortKey::Id, SortDirection::Ascending) => query.order(id.asc()),
+            (SortKey::Id, SortDirection::Descending) => query.order(id.desc()),
+            (SortKey::InsertTimestamp, SortDirection::Ascending) => {
+                query.order((timestamp.asc(), id.asc()))
+            }
+            (SortKey::InsertTimestamp, SortDirection::Descending) => {
+                query.order((timestamp.desc(), id.desc()))
+            }
+            (SortKey::HandleTimestamp, SortDirection::Ascending) => {
+                query.order((handle_ts.asc().nulls_last(), id.asc()))
+            }
+            (SortKey::HandleTimestamp, SortDirection::Descending) => {
+                query.order((handle_ts.desc().nulls_last(), id.desc()))
+            }
+            (SortKey::Relevance, SortDirection::Ascending) => {
+                query.order((search::rank(search.unwrap_or_default()).asc(), id.asc()))
+            }
+            (SortKey::Relevance, SortDirection::Descending) => {
+                query.order((search::rank(search.unwrap_or_default()).desc(), id.desc()))
+            }
+            (SortKey::Priority, SortDirection::Ascending) => {
+                query.order((priority.asc(), id.asc()))
+            }
+            (SortKey::Priority, SortDirection::Descending) => {
+                query.order((priority.desc(), id.desc()))
+            }
+        }
+    }
+
+    ///
+    /// Restrict a query to reports sorted after the given page token.
+    ///
+    fn after<'a>(
+        &self,
+        query: reports::BoxedQuery<'a, Pg>,
+        token: &PageToken,
+        search: Option<String>,
+    ) -> reports::BoxedQuery<'a, Pg> {
+        use crate::schema::reports::dsl::*;
+
+        let ascending = self.direction == SortDirection::Ascending;
+
+        match (self.key, token.value, search) {
+            (SortKey::Relevance, Some(SortValue::Rank(value)), Some(text)) => {
+                if ascending {
+                    query.filter(
+                        search::rank(text.clone())
+                            .gt(value)
+                            .or(search::rank(text).eq(value).and(id.gt(token.id))),
+                    )
+                } else {
+                    query.filter(
+                        search::rank(text.clone())
+                            .lt(value)
+                            .or(search::rank(text).eq(value).and(id.lt(token.id))),
+                    )
+                }
+            }
+            (SortKey::Id, _, _) | (SortKey::Relevance, _, _) => {
+                if ascending {
+                    query.filter(id.gt(token.id))
+                } else {
+                    query.filter(id.lt(token.id))
+                }
+            }
+            (SortKey::InsertTimestamp, Some(SortValue::Timestamp(value)), _) => {
+                if ascending {
+                    query.filter(
+                        timestamp
+                            .gt(value)
+                            .or(timestamp.eq(value).and(id.gt(token.id))),
+                    )
+                } else {
+                    query.filter(
+                        timestamp
+                            .lt(value)
+                            .or(timestamp.eq(value).and(id.lt(token.id))),
+                    )
+                }
+            }
+            (SortKey::HandleTimestamp, Some(SortValue::Timestamp(value)), _) => {
+                if ascending {
+                    query.filter(
+                        handle_ts
+                            .gt(value)
+                            .or(handle_ts.eq(value).and(id.gt(token.id)))
+                            .or(handle_ts.is_null()),
+                    )
+                } else {
+                    query.filter(
+                        handle_ts
+                            .lt(value)
+                            .or(handle_ts.eq(value).and(id.lt(token.id)))
+                            .or(handle_ts.is_null()),
+                    )
+                }
+            }
+            (SortKey::Priority, Some(SortValue::Priority(value)), _) => {
+                if ascending {
+                    query.filter(
+                        priority
+                            .gt(value)
+                            .or(priority.eq(value).and(id.gt(token.id))),
+                    )
+                } else {
+                    query.filter(
+                        priority
+                            .lt(value)
+                            .or(priority.eq(value).and(id.lt(token.id))),
+                    )
+                }
+            }
+            // Past the last handled report, only unhandled ones remain.
+            (SortKey::HandleTimestamp, None, _) => {
+                if ascending {
+                    query.filter(handle_ts.is_null().and(id.gt(token.id)))
+                } else {
+                    query.filter(handle_ts.is_null().and(id.lt(token.id)))
+                }
+            }
+            // Tokens without an insert timestamp or priority are rejected on decode.
+            _ => query.filter(id.ne(id)),
+        }
+    }
+}
+
+/// Largest amount of reports returned in a single page.
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+///
+/// Position of the last report of a page in a given order.
+///
+/// Encoded as an opaque token for clients to pass back.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageToken {
+    pub order: ReportOrder,
+    /// Sort key of the report, `None` when sorting by id or unhandled.
+    pub value: Option<SortValue>,
+    pub id: i64,
+}
+
+impl PageToken {
+    pub fn new(order: ReportOrder, report: &Report) -> Self {
+        let value = match order.key {
+            SortKey::Priority => Some(SortValue::Priority(report.priority)),
+            _ => order.timestamp(report).map(SortValue::Timestamp),
+        };
+
+        Self {
+            order,
+            value,
+            id: report.id,
+        }
+    }
+
+    ///
+    /// Set the search rank of the report, only known to the database.
+    ///
+    pub fn with_rank(mut self, rank: f32) -> Self {
+        self.value = Some(SortValue::Rank(rank));
+        self
+    }
+
+    pub fn encode(&self) -> String {
+        let key = match self.order.key {
+            SortKey::Id => 'i',
+            SortKey::InsertTimestamp => 't',
+            SortKey::HandleTimestamp => 'h',
+            SortKey::Relevance => 'r',
+            SortKey::Priority => 'p',
+        };
+
+        let direction = match self.order.direction {
+            SortDirection::Ascending => 'a',
+            SortDirection::Descending => 'd',
+        };
+
+        let value = match self.value {
+            Some(SortValue::Timestamp(ts)) => {
+                format!("{}.{:06}", ts.timestamp(), ts.timestamp_subsec_micros())
+            }
+            Some(SortValue::Rank(rank)) => rank.to_string(),
+            Some(SortValue::Priority(priority)) => priority.to_string(),
+            None => "".to_owned(),
+        };
+
+        let raw = format!("{}{}:{}:{}", key, direction, value, self.id);
+
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let raw = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+        let raw = String::from_utf8(raw).ok()?;
+
+        let mut parts = raw.splitn(3, ':');
+
+        let order = parts.next()?;
+        let value = parts.next()?;
+        let id = parts.next()?.parse::<i64>().ok()?;
+
+        let key = match order.get(0..1)? {
+            "i" => SortKey::Id,
+            "t" => SortKey::InsertTimestamp,
+            "h" => SortKey::HandleTimestamp,
+            "r" => SortKey::Relevance,
+            "p" => SortKey::Priority,
+            _ => return None,
+        };
+
+        let direction = match order.get(1..)? {
+            "a" => SortDirection::Ascending,
+            "d" => SortDirection::Descending,
+            _ => return None,
+        };
+
+        let value = if value.is_empty() {
+            None
+        } else if key == SortKey::Relevance {
+            Some(SortValue::Rank(value.parse::<f32>().ok()?))
+        } else if key == SortKey::Priority {
+            Some(SortValue::Priority(
+                value.parse::<f64>().ok().filter(|x| x.is_finite())?,
+            ))
+        } else {
+            let (seconds, micros) = value.split_once('.')?;
+
+            let seconds = seconds.parse::<i64>().ok()?;
+            let micros = micros.parse::<u32>().ok()?;
+
+            if micros >= 1_000_000 {
+                return None;
+            }
+
+            Some(SortValue::Timestamp(
+                Utc.timestamp_opt(seconds, micros * 1000).single()?,
+            ))
+        };
+
+        // Ids need no value while insert timestamps and priorities are never null.
+        match (key, value) {
+            (SortKey::Id, Some(_))
+            | (SortKey::InsertTimestamp, None)
+            | (SortKey::Priority, None) => return None,
+            _ => {}
+        }
+
+        Some(Self {
+            order: ReportOrder::new(key, direction),
+            value,
+            id,
+        })
+    }
+}
+
+///
+/// A page of reports to query.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub size: i64,
+    /// Continue after the given report, or start from the first on `None`.
+    ///
+    /// The token is only meaningful for the order it was issued in.
+    pub after: Option<PageToken>,
+}
+
+impl Page {
+    ///
+    /// Build a page from a requested size and a possibly empty token.
+    ///
+    /// The size is clamped to `MAX_PAGE_SIZE`.
+    ///
+    pub fn new(size: i64, token: &str) -> Option<Self> {
+        let after = if !token.is_empty() {
+            Some(PageToken::decode(token)?)
+        } else {
+            None
+        };
+
+        Some(Self {
+            size: size.clamp(1, MAX_PAGE_SIZE),
+            after,
+        })
+    }
+}
+
+///
+/// A page of queried reports.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportPage {
+    pub reports: Vec<Report>,
+    /// Token of the next page, `None` on the last page.
+    pub next: Option<PageToken>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [SortKey; 5] = [
+        SortKey::Id,
+        SortKey::InsertTimestamp,
+        SortKey::HandleTimestamp,
+        SortKey::Relevance,
+        SortKey::Priority,
+    ];
+
+    const DIRECTIONS: [SortDirection; 2] = [SortDirection::Ascending, SortDirection::Descending];
+
+    fn raw(raw: &str) -> String {
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn values(key: SortKey) -> Vec<Option<SortValue>> {
+        let timestamps = vec![
+            Utc.timestamp_opt(1_600_000_000, 5_000).unwrap(),
+            Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            // Before the epoch, with whole seconds rounded down.
+            Utc.timestamp_opt(-2, 500_000_000).unwrap(),
+            Utc.timestamp_opt(-1, 999_999_000).unwrap(),
+        ];
+
+        match key {
+            SortKey::Id => vec![None],
+            SortKey::InsertTimestamp => timestamps
+                .into_iter()
+                .map(|ts| Some(SortValue::Timestamp(ts)))
+                .collect(),
+            SortKey::HandleTimestamp => timestamps
+                .into_iter()
+                .map(|ts| Some(SortValue::Timestamp(ts)))
+                .chain(std::iter::once(None))
+                .collect(),
+            SortKey::Relevance => vec![None, Some(SortValue::Rank(0.0607927))],
+            SortKey::Priority => vec![
+                Some(SortValue::Priority(0.0)),
+                Some(SortValue::Priority(-2.5)),
+                Some(SortValue::Priority(41.67)),
+            ],
+        }
+    }
+
+    #[test]
+    fn page_token_round_trips() {
+        for &key in KEYS.iter() {
+            for &direction in DIRECTIONS.iter() {
+                for value in values(key) {
+                    let token = PageToken {
+                        order: ReportOrder::new(key, direction),
+                        value,
+                        id: 42,
+                    };
+
+                    assert_eq!(PageToken::decode(&token.encode()), Some(token));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn page_token_pads_micros() {
+        let token = PageToken {
+            order: ReportOrder::new(SortKey::InsertTimestamp, SortDirection::Ascending),
+            value: Some(SortValue::Timestamp(Utc.timestamp_opt(10, 5_000).unwrap())),
+            id: 1,
+        };
+
+        assert_eq!(token.encode(), raw("ta:10.000005:1"));
+    }
+
+    #[test]
+    fn page_token_rejects_invalid_combinations() {
+        for token in [
+            // Ids need no value.
+            "ia:10.0:1",
+            // Insert timestamps and priorities are never null.
+            "ta::1",
+            "pd::1",
+            "pd:NaN:1",
+            "pd:inf:1",
+            "ta:10.1000000:1",
+            "ta:10:1",
+            "xa::1",
+            "ix::1",
+            "ia::",
+            "ia::one",
+            "ia:1",
+        ]
+        .iter()
+        {
+            assert_eq!(PageToken::decode(&raw(token)), None, "{}", token);
+        }
+
+        assert_eq!(PageToken::decode("not base64!"), None);
+    }
+
+    #[test]
+    fn page_size_is_clamped() {
+        assert_eq!(Page::new(0, "").map(|page| page.size), Some(1));
+        assert_eq!(Page::new(-5, "").map(|page| page.size), Some(1));
+        assert_eq!(Page::new(50, "").map(|page| page.size), Some(50));
+        assert_eq!(
+            Page::new(MAX_PAGE_SIZE + 1, "").map(|page| page.size),
+            Some(MAX_PAGE_SIZE)
+        );
+        assert_eq!(Page::new(50, &raw("xa::1")), None);
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::schema::rate_limits;
+
+///
+/// A token bucket of `capacity` tokens refilled at `per_minute` tokens a
+/// minute, each submit taking one.
+///
+/// Limits without capacity or refill are disabled.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub per_minute: f64,
+}
+
+impl RateLimit {
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0 && self.per_minute > 0.0
+    }
+
+    fn per_second(&self) -> f64 {
+        self.per_minute / 60.0
+    }
+
+    ///
+    /// Tokens of a bucket left with `tokens` at `updated`, refilled until `at`.
+    ///
+    pub fn refill(&self, tokens: f64, updated: DateTime<Utc>, at: DateTime<Utc>) -> f64 {
+        // Instances may disagree on the time, so never drain the bucket.
+        let elapsed = (at - updated).num_milliseconds().max(0) as f64 / 1000.0;
+
+        (tokens + elapsed * self.per_second()).min(self.capacity as f64)
+    }
+
+    ///
+    /// Time until a bucket with `tokens` holds a whole token again.
+    ///
+    pub fn retry_after(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) / self.per_second()).max(0.0))
+    }
+
+    ///
+    /// Time a bucket with `tokens` at `at` is full again.
+    ///
+    pub fn full_at(&self, tokens: f64, at: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = ((self.capacity as f64 - tokens) / self.per_second()).max(0.0);
+
+        at + chrono::Duration::milliseconds((secs * 1000.0).ceil() as i64)
+    }
+}
+
+///
+/// A row of the `rate_limits` table.
+///
+#[derive(Queryable, Insertable, Debug, Clone, PartialEq)]
+#[table_name = "rate_limits"]
+pub struct RateBucket {
+    pub bucket: String,
+    pub tokens: f64,
+    pub updated: DateTime<Utc>,
+    pub full_at: DateTime<Utc>,
+}
+
+impl RateBucket {
+    ///
+    /// A full bucket of a limit at `at`.
+    ///
+    pub fn full(bucket: String, limit: &RateLimit, at: DateTime<Utc>) -> Self {
+        Self {
+            bucket,
+            tokens: limit.capacity as f64,
+            updated: at,
+            full_at: at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const LIMIT: RateLimit = RateLimit {
+        capacity: 3,
+        per_minute: 1.0,
+    };
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn refill_is_capped_at_capacity() {
+        assert_eq!(LIMIT.refill(0.0, at(0), at(30)), 0.5);
+        assert_eq!(LIMIT.refill(1.0, at(0), at(120)), 3.0);
+        assert_eq!(LIMIT.refill(0.0, at(0), at(3600)), 3.0);
+    }
+
+    #[test]
+    fn refill_ignores_clock_skew() {
+        assert_eq!(LIMIT.refill(1.5, at(60), at(0)), 1.5);
+    }
+
+    #[test]
+    fn retry_after_waits_for_whole_token() {
+        assert_eq!(LIMIT.retry_after(0.0), Duration::from_secs(60));
+        assert_eq!(LIMIT.retry_after(0.75), Duration::from_secs(15));
+        assert_eq!(LIMIT.retry_after(1.0), Duration::from_secs(0));
+        assert_eq!(LIMIT.retry_after(2.0), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn full_at_refills_missing_tokens() {
+        assert_eq!(LIMIT.full_at(3.0, at(0)), at(0));
+        assert_eq!(LIMIT.full_at(1.0, at(0)), at(120));
+        assert_eq!(LIMIT.full_at(2.5, at(0)), at(30));
+    }
+
+    #[test]
+    fn empty_limits_are_disabled() {
+        assert!(LIMIT.is_enabled());
+        assert!(!RateLimit {
+            capacity: 0,
+            per_minute: 1.0
+        }
+        .is_enabled());
+        assert!(!RateLimit {
+            capacity: 3,
+            per_minute: 0.0
+        }
+        .is_enabled());
+    }
+}
@@ -1,14 +1,113 @@
+table! {
+    rate_limits (bucket) {
+        bucket -> Text,
+        tokens -> Float8,
+        updated -> Timestamptz,
+        full_at -> Timestamptz,
+    }
+}
+
 table! {
     reports (id) {
         id -> Int8,
         active -> Bool,
-        timestamp -> Int8,
+        timestamp -> Timestamptz,
         reporter -> Text,
         reported -> Text,
         handler -> Nullable<Text>,
-        handle_ts -> Nullable<Int8>,
+        handle_ts -> Nullable<Timestamptz>,
         comment -> Nullable<Text>,
         description -> Text,
-        tags -> Nullable<Text>,
+        server -> Nullable<Text>,
+        status -> Text,
+        claimant -> Nullable<Text>,
+        claim_expires -> Nullable<Timestamptz>,
+        outcome -> Nullable<Text>,
+        parent_id -> Nullable<Int8>,
+        duplicates -> Int4,
+        priority -> Float8,
+    }
+}
+
+table! {
+    report_tags (report_id, tag) {
+        report_id -> Int8,
+        tag -> Text,
+    }
+}
+
+table! {
+    report_events (id) {
+        id -> Int8,
+        report_id -> Int8,
+        kind -> Text,
+        actor -> Nullable<Text>,
+        timestamp -> Timestamptz,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    report_notes (id) {
+        id -> Int8,
+        report_id -> Int8,
+        author -> Text,
+        body -> Text,
+        created -> Timestamptz,
+        edited -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    report_note_edits (id) {
+        id -> Int8,
+        note_id -> Int8,
+        editor -> Text,
+        timestamp -> Timestamptz,
+        body -> Text,
+    }
+}
+
+table! {
+    report_outbox (id) {
+        id -> Int8,
+        report_id -> Int8,
+        kind -> Text,
+        payload -> Bytea,
+        attempts -> Int4,
+        next_attempt -> Timestamptz,
+        delivered_to -> Array<Text>,
+        last_error -> Nullable<Text>,
+        created -> Timestamptz,
+        failed_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    transporters (address) {
+        address -> Text,
+        node -> Text,
+        registered -> Timestamptz,
+        heartbeat -> Timestamptz,
+        expires -> Timestamptz,
     }
 }
+
+joinable!(report_events -> reports (report_id));
+joinable!(report_note_edits -> report_notes (note_id));
+joinable!(report_notes -> reports (report_id));
+joinable!(report_outbox -> reports (report_id));
+joinable!(report_tags -> reports (report_id));
+
+allow_tables_to_appear_in_same_query!(
+    rate_limits,
+    report_events,
+    report_note_edits,
+    report_notes,
+    report_outbox,
+    report_tags,
+    reports,
+    transporters,
+);
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use diesel::dsl::sql;
+use diesel::expression::BoxableExpression;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, Bool, Float, Text};
+
+use crate::schema::reports;
+
+// The generated `reports.search` column is left out of `schema.rs` as
+// diesel has no `tsvector` type, so it is only ever used through SQL.
+
+///
+/// Reports whose description or comment match a web search style query.
+///
+pub fn matches(query: String) -> Box<dyn BoxableExpression<reports::table, Pg, SqlType = Bool>> {
+    Box::new(
+        sql::<Bool>("reports.search @@ websearch_to_tsquery('english', ")
+            .bind::<Text, _>(query)
+            .sql(")"),
+    )
+}
+
+///
+/// Relevance of a report to a web search style query.
+///
+/// Reports not matching the query rank 0.
+///
+pub fn rank(query: String) -> Box<dyn BoxableExpression<reports::table, Pg, SqlType = Float>> {
+    Box::new(
+        sql::<Float>("ts_rank(reports.search, websearch_to_tsquery('english', ")
+            .bind::<Text, _>(query)
+            .sql("))"),
+    )
+}
+
+#[derive(QueryableByName)]
+struct Snippet {
+    #[sql_type = "BigInt"]
+    id: i64,
+    #[sql_type = "Text"]
+    snippet: String,
+}
+
+/// Marks the start of a match in snippets, STX.
+pub const MATCH_START: char = '\u{2}';
+
+/// Marks the end of a match in snippets, ETX.
+pub const MATCH_END: char = '\u{3}';
+
+///
+/// Load the description or comment of reports with the matches of a
+/// web search style query between `MATCH_START` and `MATCH_END`.
+///
+/// Snippets are player written text left as is besides the markers,
+/// which are removed from the text itself.
+///
+pub fn snippets(
+    conn: &PgConnection,
+    query: &str,
+    ids: Vec<i64>,
+) -> QueryResult<HashMap<i64, String>> {
+    let loaded = diesel::sql_query(
+        "SELECT id, ts_headline('english', \
+             translate(description || coalesce(E'\\n' || comment, ''), $3, ''), \
+             websearch_to_tsquery('english', $1), $4) AS snippet \
+         FROM reports WHERE id = ANY($2)",
+    )
+    .bind::<Text, _>(query)
+    .bind::<Array<BigInt>, _>(ids)
+    .bind::<Text, _>(format!("{}{}", MATCH_START, MATCH_END))
+    .bind::<Text, _>(format!(
+        "MaxFragments=2, StartSel={}, StopSel={}",
+        MATCH_START, MATCH_END
+    ))
+    .load::<Snippet>(conn)?;
+
+    Ok(loaded
+        .into_iter()
+        .map(|loaded| (loaded.id, loaded.snippet))
+        .collect())
+}
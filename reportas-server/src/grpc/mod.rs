@@ -1,2 +1,42 @@
 pub mod report_handler;
+pub mod report_handler1_0;
 pub mod report_transporter;
+
+use crate::report_handler::Error;
+
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+
+impl From<Error> for Status {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::RateLimited(retry_after) => {
+                let mut status = Status::resource_exhausted(e.to_string());
+                let metadata = status.metadata_mut();
+
+                // Whole seconds as in HTTP, rounded up.
+                let secs = (retry_after.as_millis() as u64).div_ceil(1000);
+
+                metadata.insert("retry-after", MetadataValue::from(secs));
+                metadata.insert(
+                    "retry-after-ms",
+                    MetadataValue::from(retry_after.as_millis() as u64),
+                );
+
+                status
+            }
+            Error::DatabaseFailed => Status::failed_precondition(e.to_string()),
+            Error::InvalidTimestamp => Status::invalid_argument(e.to_string()),
+            Error::InvalidPageToken => Status::invalid_argument(e.to_string()),
+            Error::ReportNotFound => Status::not_found(e.to_string()),
+            Error::NoteNotFound => Status::not_found(e.to_string()),
+            Error::NotNoteAuthor => Status::permission_denied(e.to_string()),
+            Error::IllegalTransition { .. } => Status::failed_precondition(e.to_string()),
+            Error::MissingOutcome => Status::invalid_argument(e.to_string()),
+            Error::ReportClaimed(_) => Status::failed_precondition(e.to_string()),
+            Error::MergeIntoItself => Status::failed_precondition(e.to_string()),
+            Error::InvalidTransporterAddress => Status::invalid_argument(e.to_string()),
+            Error::TransporterNotRegistered => Status::not_found(e.to_string()),
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use std::convert::TryInto;
+use std::pin::Pin;
 use std::sync::Arc;
 
-use crate::report_handler::Error;
-use crate::report_handler::ReportHandler;
+use crate::report_handler::{ReportHandler, ReportStreamPage};
 
 use service::report::report_handler_server;
 use service::report::IdentifiedReportMessage;
@@ -9,35 +10,64 @@ use service::report::ReportDeactivateRequest;
 use service::report::ReportQuery;
 use service::report::ReportRequest;
 
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
+use futures_util::TryStreamExt;
+use tokio_stream::Stream;
 use tonic::Request;
 use tonic::Response;
 use tonic::Status;
 use tracing::info;
 
 pub struct GrpcReportHandler {
-    handler: ReportHandler,
+    handler: Arc<ReportHandler>,
 }
 
 impl GrpcReportHandler {
-    pub async fn new(addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let handler = ReportHandler::new(addr).await?;
+    pub fn new(handler: Arc<ReportHandler>) -> Self {
+        GrpcReportHandler { handler }
+    }
+}
 
-        Ok(GrpcReportHandler { handler })
+///
+/// Streamed response of the legacy query RPCs.
+///
+type ReportMessageStream =
+    Pin<Box<dyn Stream<Item = Result<IdentifiedReportMessage, Status>> + Send>>;
+
+///
+/// Stream a page of reports to the client, attaching the token of the
+/// next page to the response metadata.
+///
+/// Reports are only pulled from the database as the client consumes
+/// them, and dropping the response stops the query.
+///
+fn stream_page(page: ReportStreamPage) -> Response<ReportMessageStream> {
+    let stream: ReportMessageStream = Box::pin(
+        page.reports
+            .map_ok(IdentifiedReportMessage::from)
+            .map_err(Status::from),
+    );
+
+    let mut response = Response::new(stream);
+
+    if let Some(next) = page.next {
+        if let Ok(token) = next.encode().parse() {
+            response.metadata_mut().insert("next-page-token", token);
+        }
     }
+
+    response
 }
 
 #[tonic::async_trait]
 impl report_handler_server::ReportHandler for GrpcReportHandler {
-    type QueryAllReportsStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByReporterStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByReportedStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByActiveStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByTimestampStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByHandlerStream = ReceiverStream<Result<IdentifiedReportMessage, Status>>;
-    type QueryReportsByHandleTimestampStream =
-        ReceiverStream<Result<IdentifiedReportMessage, Status>>;
+    type QueryAllReportsStream = ReportMessageStream;
+    type QueryReportsByReporterStream = ReportMessageStream;
+    type QueryReportsByReportedStream = ReportMessageStream;
+    type QueryReportsByActiveStream = ReportMessageStream;
+    type QueryReportsByTimestampStream = ReportMessageStream;
+    type QueryReportsByHandlerStream = ReportMessageStream;
+    type QueryReportsByServerStream = ReportMessageStream;
+    type QueryReportsByHandleTimestampStream = ReportMessageStream;
 
     async fn submit_report(
         &self,
@@ -53,17 +83,7 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
 
         let rep = match self.handler.submit_report(req_msg.clone().into()).await {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
         info!("\n\nrpc#SubmitReport :: ({:?}) \n\n{:?}\n", &req_msg, &rep);
@@ -77,19 +97,11 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<IdentifiedReportMessage>, tonic::Status> {
         let rdr = request.into_inner();
 
-        let rep = match self.handler.deactivate_report(rdr.clone().into()).await {
+        let req = rdr.clone().try_into().map_err(Status::invalid_argument)?;
+
+        let rep = match self.handler.deactivate_report(req).await {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
         info!("\n\nrpc#DeactivateReport :: ({:?}) \n\n{:?}\n", &rdr, &rep);
@@ -106,45 +118,14 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryAllReportsStream>, Status> {
         let req = request.into_inner();
 
-        let res = match self.handler.query_all_reports().await {
+        let page = match self.handler.query_all_reports(req.clone().into()).await {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        info!(
-            "\n\nrpc#QueryAllReports :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
-
-        let mut irms = Vec::<IdentifiedReportMessage>::new();
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
-
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
+        info!("\n\nrpc#QueryAllReports :: ({:?}) \n", &req);
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     ///
@@ -157,48 +138,18 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByReporterStream>, Status> {
         let req = request.into_inner();
 
-        let res = match self
+        let page = match self
             .handler
             .query_reports_by_reporter(req.clone().into())
             .await
         {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
-
-        info!(
-            "\n\nrpc#QueryReportsByReporter :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
+        info!("\n\nrpc#QueryReportsByReporter :: ({:?}) \n", &req);
 
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
-
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     ///
@@ -211,48 +162,18 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByReportedStream>, Status> {
         let req = request.into_inner();
 
-        let res = match self
+        let page = match self
             .handler
             .query_reports_by_reported(req.clone().into())
             .await
         {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
-
-        info!(
-            "\n\nrpc#QueryReportsByReported :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
-
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
+        info!("\n\nrpc#QueryReportsByReported :: ({:?}) \n", &req);
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     async fn query_reports_by_timestamp(
@@ -261,48 +182,18 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByTimestampStream>, tonic::Status> {
         let req = request.into_inner();
 
-        let res = match self
+        let page = match self
             .handler
             .query_reports_by_timestamp(req.clone().into())
             .await
         {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        info!(
-            "\n\nrpc#QueryReportsByTimestamp :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
+        info!("\n\nrpc#QueryReportsByTimestamp :: ({:?}) \n", &req);
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
-
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
-
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     ///
@@ -317,17 +208,7 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
 
         let res = match self.handler.query_reports_by_id(req.clone().into()).await {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
         info!("\n\nrpc#QueryReportsById :: ({:?}) \n", &req);
@@ -337,8 +218,18 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
         }
 
         let res0 = res[0].clone();
+        let mut irm: IdentifiedReportMessage = res0.into();
+
+        if req.include_notes {
+            let notes = match self.handler.report_notes(irm.id).await {
+                Ok(val) => val,
+                Err(e) => return Err(e.into()),
+            };
 
-        Ok(Response::new(res0.into()))
+            irm.notes = notes.into_iter().map(|note| note.into()).collect();
+        }
+
+        Ok(Response::new(irm))
     }
 
     async fn query_reports_by_handler(
@@ -347,48 +238,43 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByHandlerStream>, Status> {
         let req = request.into_inner();
 
-        let res = match self
+        let page = match self
             .handler
             .query_reports_by_handler(req.clone().into())
             .await
         {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
+        info!("\n\nrpc#QueryReportsByHandler :: ({:?}) \n", &req);
 
-        info!(
-            "\n\nrpc#QueryReportsByHandler :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
+        Ok(stream_page(page))
+    }
 
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
+    ///
+    /// Query identified reports from the database / cache by the
+    /// server node they originate from. An empty query matches
+    /// reports without a server node.
+    ///
+    async fn query_reports_by_server(
+        &self,
+        request: Request<ReportQuery>,
+    ) -> Result<Response<Self::QueryReportsByServerStream>, Status> {
+        let req = request.into_inner();
 
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
+        let page = match self
+            .handler
+            .query_reports_by_server(req.clone().into())
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
 
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
+        info!("\n\nrpc#QueryReportsByServer :: ({:?}) \n", &req);
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     async fn query_reports_by_handle_timestamp(
@@ -397,48 +283,18 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByHandleTimestampStream>, Status> {
         let req = request.into_inner();
 
-        let res = match self
+        let page = match self
             .handler
             .query_reports_by_handle_timestamp(req.clone().into())
             .await
         {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
+        info!("\n\nrpc#QueryReportsByHandleTimestamp :: ({:?}) \n", &req);
 
-        info!(
-            "\n\nrpc#QueryReportsByHandleTimestamp :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
-
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
-
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 
     async fn query_reports_by_active(
@@ -447,43 +303,17 @@ impl report_handler_server::ReportHandler for GrpcReportHandler {
     ) -> Result<Response<Self::QueryReportsByActiveStream>, tonic::Status> {
         let req = request.into_inner();
 
-        let res = match self.handler.query_reports_by_active().await {
+        let page = match self
+            .handler
+            .query_reports_by_active(req.clone().into())
+            .await
+        {
             Ok(val) => val,
-            Err(e) => match e {
-                Error::DatabaseFailed => {
-                    return Err(Status::failed_precondition(e.to_string()));
-                }
-                Error::TransportError => {
-                    return Err(Status::aborted(e.to_string()));
-                }
-                Error::InvalidTimestamp => {
-                    return Err(Status::invalid_argument(e.to_string()));
-                }
-            },
+            Err(e) => return Err(e.into()),
         };
 
-        let mut irms: Vec<IdentifiedReportMessage> = Vec::new();
-
-        info!(
-            "\n\nrpc#QueryReportsByActive :: ({:?}) \n\nGot {} reports to stream\n",
-            &req,
-            &res.len()
-        );
-
-        for rep in res.into_iter() {
-            let irm = rep.into();
-            irms.push(irm);
-        }
-
-        let (tx, rx) = mpsc::channel(4);
-        let res = Arc::new(irms);
-
-        tokio::spawn(async move {
-            for result in &res[..] {
-                tx.send(Ok(result.clone())).await.unwrap();
-            }
-        });
+        info!("\n\nrpc#QueryReportsByActive :: ({:?}) \n", &req);
 
-        Ok(Response::new(ReceiverStream::new(rx)))
+        Ok(stream_page(page))
     }
 }
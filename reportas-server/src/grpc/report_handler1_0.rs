@@ -0,0 +1,681 @@
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+
+use crate::report_bus::SubscribeFilter;
+use crate::report_handler::{Error, ReportHandler};
+
+use service::models::{
+    from_proto_outcome, from_proto_status, from_proto_timestamp, ReportTransition,
+};
+use service::query::MAX_PAGE_SIZE;
+use service::report1_0::report_filter::Predicate;
+use service::report1_0::report_handler_server;
+use service::report1_0::report_query_request::Filter;
+use service::report1_0::report_subscribe_filter::Server;
+use service::report1_0::{
+    ReportBroadcast, ReportClaimRequest, ReportClaimResponse, ReportDeactivateRequest,
+    ReportDeactivateResponse, ReportHistoryRequest, ReportHistoryResponse, ReportInsertRequest,
+    ReportInsertResponse, ReportMergeRequest, ReportMergeResponse, ReportNoteAddRequest,
+    ReportNoteEditRequest, ReportNoteListRequest, ReportNoteListResponse, ReportNoteResponse,
+    ReportQueryRequest, ReportQueryResponse, ReportReactivateRequest, ReportReactivateResponse,
+    ReportReleaseRequest, ReportReleaseResponse, ReportSortKey, ReportStatusRequest,
+    ReportStatusResponse, ReportSubscribeRequest, ServerNodeConstant, TimeRange, TransporterLease,
+    TransporterListRequest, TransporterListResponse, TransporterRegisterRequest,
+    TransporterRenewRequest,
+};
+use service::{Page, ReportFilter, ReportFilterSet, ReportOrder, SortDirection, SortKey};
+
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tracing::{debug, info, warn};
+
+///
+/// gRPC frontend for the `report1_0.ReportHandler` service.
+///
+/// Shares the underlying `ReportHandler` with the legacy
+/// `report.ReportHandler` service.
+///
+pub struct GrpcReportHandler1_0 {
+    handler: Arc<ReportHandler>,
+}
+
+impl GrpcReportHandler1_0 {
+    pub fn new(handler: Arc<ReportHandler>) -> Self {
+        GrpcReportHandler1_0 { handler }
+    }
+}
+
+///
+/// Convert a 1.0 query predicate to a `ReportFilter`.
+///
+/// An empty `handler` matches reports not handled by anyone, and an empty
+/// server node identifier reports not originating from a specific node.
+///
+fn report_filter(predicate: Predicate) -> Result<ReportFilter, &'static str> {
+    let filter = match predicate {
+        Predicate::Id(id) => ReportFilter::Id(id),
+        Predicate::Reported(reported) => ReportFilter::Reported(reported),
+        Predicate::Reporter(reporter) => ReportFilter::Reporter(reporter),
+        Predicate::Active(active) => ReportFilter::Active(active),
+        Predicate::Outcome(outcomes) => {
+            let mut values = Vec::new();
+
+            for outcome in outcomes.outcomes {
+                match from_proto_outcome(outcome)? {
+                    Some(val) => values.push(val),
+                    None => return Err("missing resolution outcome"),
+                }
+            }
+
+            if values.is_empty() {
+                return Err("empty outcome filter");
+            }
+
+            ReportFilter::Outcome(values)
+        }
+        Predicate::Status(statuses) => {
+            if statuses.statuses.is_empty() {
+                return Err("empty status filter");
+            }
+
+            ReportFilter::Status(
+                statuses
+                    .statuses
+                    .into_iter()
+                    .map(from_proto_status)
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Predicate::Handler(handler) => {
+            if !handler.is_empty() {
+                ReportFilter::Handler(Some(handler))
+            } else {
+                ReportFilter::Handler(None)
+            }
+        }
+        Predicate::InsertTimestamp(ts) => {
+            ReportFilter::InsertTimestamp(from_proto_timestamp(&ts).ok_or("invalid timestamp")?)
+        }
+        Predicate::HandleTimestamp(ts) => {
+            ReportFilter::HandleTimestamp(from_proto_timestamp(&ts).ok_or("invalid timestamp")?)
+        }
+        Predicate::ServerNode(node) => {
+            if !node.identifier.is_empty() {
+                ReportFilter::Server(Some(node.identifier))
+            } else {
+                ReportFilter::Server(None)
+            }
+        }
+        Predicate::TagsAny(tags) => ReportFilter::TagsAny(tags.into()),
+        Predicate::TagsAll(tags) => ReportFilter::TagsAll(tags.into()),
+        Predicate::InsertRange(range) => ReportFilter::InsertRange(time_range(range)?),
+        Predicate::HandleRange(range) => ReportFilter::HandleRange(time_range(range)?),
+        Predicate::Search(text) => {
+            if text.trim().is_empty() {
+                return Err("empty search");
+            }
+
+            ReportFilter::Search(text)
+        }
+        Predicate::MinPriority(priority) => {
+            if !priority.is_finite() {
+                return Err("invalid priority");
+            }
+
+            ReportFilter::MinPriority(priority)
+        }
+    };
+
+    Ok(filter)
+}
+
+///
+/// Convert a 1.0 time range to a `service::TimeRange`.
+///
+fn time_range(range: TimeRange) -> Result<service::TimeRange, &'static str> {
+    let from = match range.from {
+        Some(ts) => Some(from_proto_timestamp(&ts).ok_or("invalid timestamp")?),
+        None => None,
+    };
+
+    let to = match range.to {
+        Some(ts) => Some(from_proto_timestamp(&ts).ok_or("invalid timestamp")?),
+        None => None,
+    };
+
+    Ok(service::TimeRange::new(from, to))
+}
+
+///
+/// Convert the order of a 1.0 query request to a `ReportOrder`.
+///
+/// A request without an order sorts by ascending insert time.
+///
+fn report_order(req: &ReportQueryRequest) -> Result<ReportOrder, &'static str> {
+    let order = match &req.order {
+        Some(val) => val,
+        None => return Ok(ReportOrder::default()),
+    };
+
+    let key = match ReportSortKey::from_i32(order.key) {
+        Some(ReportSortKey::Id) => SortKey::Id,
+        Some(ReportSortKey::InsertTimestamp) => SortKey::InsertTimestamp,
+        Some(ReportSortKey::HandleTimestamp) => SortKey::HandleTimestamp,
+        Some(ReportSortKey::Relevance) => SortKey::Relevance,
+        Some(ReportSortKey::Priority) => SortKey::Priority,
+        None => return Err("invalid sort key"),
+    };
+
+    let direction = match service::report1_0::SortDirection::from_i32(order.direction) {
+        Some(service::report1_0::SortDirection::Ascending) => SortDirection::Ascending,
+        Some(service::report1_0::SortDirection::Descending) => SortDirection::Descending,
+        None => return Err("invalid sort direction"),
+    };
+
+    Ok(ReportOrder::new(key, direction))
+}
+
+///
+/// Convert a 1.0 query request to a `ReportFilterSet`.
+///
+fn filter_set(req: ReportQueryRequest) -> Result<ReportFilterSet, &'static str> {
+    let filters = match req.filter {
+        Some(Filter::All(_)) => return Ok(ReportFilterSet::all()),
+        Some(Filter::Filters(set)) => set.filters,
+        None => return Err("missing filter"),
+    };
+
+    let mut set = ReportFilterSet::all();
+
+    for filter in filters {
+        match filter.predicate {
+            Some(predicate) => set = set.with(report_filter(predicate)?),
+            None => return Err("missing predicate"),
+        }
+    }
+
+    Ok(set)
+}
+
+///
+/// Convert a 1.0 subscribe request to a `SubscribeFilter`.
+///
+/// A request without a filter subscribes globally.
+///
+fn subscribe_filter(req: ReportSubscribeRequest) -> Result<SubscribeFilter, &'static str> {
+    match req.filter.and_then(|f| f.server) {
+        None => Ok(SubscribeFilter::Global),
+        Some(Server::Node(node)) => Ok(SubscribeFilter::Node(node.identifier)),
+        Some(Server::Constant(constant)) => match ServerNodeConstant::from_i32(constant) {
+            Some(ServerNodeConstant::Global) => Ok(SubscribeFilter::Global),
+            _ => Err("cannot subscribe to an undefined server node"),
+        },
+    }
+}
+
+#[tonic::async_trait]
+impl report_handler_server::ReportHandler for GrpcReportHandler1_0 {
+    type SubscribeReportStream = ReceiverStream<Result<ReportBroadcast, Status>>;
+
+    async fn insert_report(
+        &self,
+        request: Request<ReportInsertRequest>,
+    ) -> Result<Response<ReportInsertResponse>, Status> {
+        let report = match request.into_inner().report {
+            Some(val) => val,
+            None => {
+                return Err(Status::invalid_argument("invalid argument"));
+            }
+        };
+
+        let rep = match self.handler.submit_report(report.clone().into()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#InsertReport :: ({:?}) \n\n{:?}\n",
+            &report, &rep
+        );
+
+        Ok(Response::new(ReportInsertResponse {
+            report: Some(rep.into()),
+        }))
+    }
+
+    async fn deactivate_report(
+        &self,
+        request: Request<ReportDeactivateRequest>,
+    ) -> Result<Response<ReportDeactivateResponse>, Status> {
+        let rdr = request.into_inner();
+
+        let req = rdr.clone().try_into().map_err(Status::invalid_argument)?;
+
+        let rep = match self.handler.deactivate_report(req).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#DeactivateReport :: ({:?}) \n\n{:?}\n",
+            &rdr, &rep
+        );
+
+        Ok(Response::new(ReportDeactivateResponse {
+            report: Some(rep.into()),
+        }))
+    }
+
+    async fn reactivate_report(
+        &self,
+        request: Request<ReportReactivateRequest>,
+    ) -> Result<Response<ReportReactivateResponse>, Status> {
+        let rrr = request.into_inner();
+
+        let rep = match self.handler.reactivate_report(rrr.clone().into()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#ReactivateReport :: ({:?}) \n\n{:?}\n",
+            &rrr, &rep
+        );
+
+        Ok(Response::new(ReportReactivateResponse {
+            report: Some(rep.into()),
+        }))
+    }
+
+    async fn update_report_status(
+        &self,
+        request: Request<ReportStatusRequest>,
+    ) -> Result<Response<ReportStatusResponse>, Status> {
+        let rsr = request.into_inner();
+
+        let transition =
+            ReportTransition::try_from(rsr.clone()).map_err(Status::invalid_argument)?;
+
+        let rep = match self.handler.transition_report(transition).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#UpdateReportStatus :: ({:?}) \n\n{:?}\n",
+            &rsr, &rep
+        );
+
+        Ok(Response::new(ReportStatusResponse {
+            report: Some(rep.into()),
+        }))
+    }
+
+    async fn claim_report(
+        &self,
+        request: Request<ReportClaimRequest>,
+    ) -> Result<Response<ReportClaimResponse>, Status> {
+        let rcr = request.into_inner();
+
+        if rcr.handler.is_empty() {
+            return Err(Status::invalid_argument("missing handler"));
+        }
+
+        let rep = match self.handler.claim_report(rcr.id, rcr.handler.clone()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("\n\nrpc1_0#ClaimReport :: ({:?}) \n\n{:?}\n", &rcr, &rep);
+
+        Ok(Response::new(ReportClaimResponse {
+            report: Some(rep.into()),
+            lease_ms: self.handler.claim_lease().as_millis() as i64,
+        }))
+    }
+
+    async fn release_report(
+        &self,
+        request: Request<ReportReleaseRequest>,
+    ) -> Result<Response<ReportReleaseResponse>, Status> {
+        let rrr = request.into_inner();
+
+        let rep = match self.handler.release_report(rrr.clone().into()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("\n\nrpc1_0#ReleaseReport :: ({:?}) \n\n{:?}\n", &rrr, &rep);
+
+        Ok(Response::new(ReportReleaseResponse {
+            report: Some(rep.into()),
+        }))
+    }
+
+    async fn add_note(
+        &self,
+        request: Request<ReportNoteAddRequest>,
+    ) -> Result<Response<ReportNoteResponse>, Status> {
+        let req = request.into_inner();
+
+        if req.author.is_empty() {
+            return Err(Status::invalid_argument("missing author"));
+        }
+
+        if req.body.trim().is_empty() {
+            return Err(Status::invalid_argument("empty note"));
+        }
+
+        let note = match self
+            .handler
+            .add_note(req.report_id, req.author.clone(), req.body.clone())
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("\n\nrpc1_0#AddNote :: ({:?}) \n\n{:?}\n", &req, &note);
+
+        Ok(Response::new(ReportNoteResponse {
+            note: Some(note.into()),
+        }))
+    }
+
+    async fn list_notes(
+        &self,
+        request: Request<ReportNoteListRequest>,
+    ) -> Result<Response<ReportNoteListResponse>, Status> {
+        let req = request.into_inner();
+
+        let threads = match self
+            .handler
+            .list_notes(req.report_id, req.include_edits)
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#ListNotes :: ({:?}) \n\nGot {} notes\n",
+            &req,
+            threads.len()
+        );
+
+        let notes = threads
+            .into_iter()
+            .map(|thread| {
+                let mut note: service::report1_0::ReportNote = thread.note.into();
+                note.edits = thread.edits.into_iter().map(|edit| edit.into()).collect();
+                note
+            })
+            .collect();
+
+        Ok(Response::new(ReportNoteListResponse { notes }))
+    }
+
+    async fn edit_note(
+        &self,
+        request: Request<ReportNoteEditRequest>,
+    ) -> Result<Response<ReportNoteResponse>, Status> {
+        let req = request.into_inner();
+
+        if req.editor.is_empty() {
+            return Err(Status::invalid_argument("missing editor"));
+        }
+
+        if req.body.trim().is_empty() {
+            return Err(Status::invalid_argument("empty note"));
+        }
+
+        let note = match self
+            .handler
+            .edit_note(req.id, req.editor.clone(), req.body.clone())
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!("\n\nrpc1_0#EditNote :: ({:?}) \n\n{:?}\n", &req, &note);
+
+        Ok(Response::new(ReportNoteResponse {
+            note: Some(note.into()),
+        }))
+    }
+
+    async fn merge_reports(
+        &self,
+        request: Request<ReportMergeRequest>,
+    ) -> Result<Response<ReportMergeResponse>, Status> {
+        let rmr = request.into_inner();
+
+        if rmr.operator.is_empty() {
+            return Err(Status::invalid_argument("missing operator"));
+        }
+
+        let merged = match self.handler.merge_reports(rmr.clone().into()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#MergeReports :: ({:?}) \n\n{:?}\n",
+            &rmr, &merged
+        );
+
+        Ok(Response::new(ReportMergeResponse {
+            report: Some(merged.report.into()),
+            parent: Some(merged.parent.into()),
+        }))
+    }
+
+    async fn query_report(
+        &self,
+        request: Request<ReportQueryRequest>,
+    ) -> Result<Response<ReportQueryResponse>, Status> {
+        let req = request.into_inner();
+
+        let filters = filter_set(req.clone()).map_err(Status::invalid_argument)?;
+        let order = report_order(&req).map_err(Status::invalid_argument)?;
+
+        // Unpaged queries could outgrow the largest message, so pages default
+        // to the largest size.
+        let page_size = if req.page_size > 0 {
+            req.page_size
+        } else {
+            MAX_PAGE_SIZE
+        };
+
+        let page = match Page::new(page_size, &req.page_token) {
+            Some(val) => Some(val),
+            None => return Err(Error::InvalidPageToken.into()),
+        };
+
+        let res = match self
+            .handler
+            .query_reports_page(filters.clone(), order, page)
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        let snippets = match self.handler.search_snippets(&filters, &res.reports).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#QueryReport :: ({:?}) \n\nGot {} reports\n",
+            &req,
+            &res.reports.len()
+        );
+
+        Ok(Response::new(ReportQueryResponse {
+            reports: res.reports.into_iter().map(|rep| rep.into()).collect(),
+            next_page_token: res.next.map(|next| next.encode()).unwrap_or_default(),
+            snippets,
+        }))
+    }
+
+    ///
+    /// Stream inserted and deactivated reports matching the
+    /// subscriber's filter until the client disconnects.
+    ///
+    async fn subscribe_report(
+        &self,
+        request: Request<ReportSubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeReportStream>, Status> {
+        let req = request.into_inner();
+
+        let filter = subscribe_filter(req.clone()).map_err(Status::invalid_argument)?;
+        let mut events = self.handler.subscribe();
+
+        info!("\n\nrpc1_0#SubscribeReport :: ({:?}) \n", &req);
+
+        let (tx, rx) = mpsc::channel(4);
+
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = tx.closed() => break,
+                    event = events.recv() => event,
+                };
+
+                match event {
+                    Ok(event) => {
+                        if !filter.matches(&event) {
+                            continue;
+                        }
+
+                        if tx.send(Ok(event.into())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Subscriber ({:?}) lagged behind by {} events",
+                            &filter, skipped
+                        );
+
+                        let _ = tx
+                            .send(Err(Status::data_loss("subscriber lagged behind")))
+                            .await;
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_report_history(
+        &self,
+        request: Request<ReportHistoryRequest>,
+    ) -> Result<Response<ReportHistoryResponse>, Status> {
+        let req = request.into_inner();
+
+        let history = match self.handler.report_history(req.id).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#GetReportHistory :: ({:?}) \n\nGot {} events\n",
+            &req,
+            history.len()
+        );
+
+        Ok(Response::new(ReportHistoryResponse {
+            events: history.into_iter().map(|event| event.into()).collect(),
+        }))
+    }
+
+    async fn register_transporter(
+        &self,
+        request: Request<TransporterRegisterRequest>,
+    ) -> Result<Response<TransporterLease>, Status> {
+        let req = request.into_inner();
+
+        let node = match &req.node {
+            Some(node) if !node.identifier.is_empty() => node.identifier.clone(),
+            _ => return Err(Status::invalid_argument("missing server node")),
+        };
+
+        let registration = match self
+            .handler
+            .register_transporter(req.address.clone(), node)
+            .await
+        {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#RegisterTransporter :: ({:?}) \n\n{:?}\n",
+            &req, &registration
+        );
+
+        Ok(Response::new(TransporterLease {
+            registration: Some(registration.into()),
+            lease_ms: self.handler.transporter_lease().as_millis() as i64,
+        }))
+    }
+
+    async fn renew_transporter(
+        &self,
+        request: Request<TransporterRenewRequest>,
+    ) -> Result<Response<TransporterLease>, Status> {
+        let req = request.into_inner();
+
+        let registration = match self.handler.renew_transporter(req.address.clone()).await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        debug!(
+            "\n\nrpc1_0#RenewTransporter :: ({:?}) \n\n{:?}\n",
+            &req, &registration
+        );
+
+        Ok(Response::new(TransporterLease {
+            registration: Some(registration.into()),
+            lease_ms: self.handler.transporter_lease().as_millis() as i64,
+        }))
+    }
+
+    async fn list_transporters(
+        &self,
+        request: Request<TransporterListRequest>,
+    ) -> Result<Response<TransporterListResponse>, Status> {
+        let req = request.into_inner();
+
+        let list = match self.handler.list_transporters().await {
+            Ok(val) => val,
+            Err(e) => return Err(e.into()),
+        };
+
+        info!(
+            "\n\nrpc1_0#ListTransporters :: ({:?}) \n\nGot {} configured, {} registered\n",
+            &req,
+            list.configured.len(),
+            list.registered.len()
+        );
+
+        Ok(Response::new(TransporterListResponse {
+            configured: list.configured,
+            registered: list
+                .registered
+                .into_iter()
+                .map(|registration| registration.into())
+                .collect(),
+        }))
+    }
+}
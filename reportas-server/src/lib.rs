@@ -2,337 +2,2007 @@ pub mod data;
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
+pub use data::cache;
 pub use data::models;
+pub use data::notify;
+pub use data::priority;
+pub use data::query;
+pub use data::rate_limit;
 pub use data::schema;
+pub use data::search;
+
+pub use priority::PriorityWeights;
+pub use query::{
+    Page, PageToken, ReportFilter, ReportFilterSet, ReportOrder, ReportPage, SortDirection,
+    SortKey, SortValue, TimeRange,
+};
+pub use rate_limit::RateLimit;
 
 extern crate dotenv;
 
 use diesel::{insert_into, pg::PgConnection, update};
 use diesel::{prelude::*, r2d2::ConnectionManager};
 
-use tokio::sync::RwLock;
-use tokio_diesel::AsyncRunQueryDsl;
+use tokio::sync::{mpsc, Mutex};
+use tokio_diesel::{AsyncConnection, AsyncError, AsyncRunQueryDsl};
+use tokio_stream::wrappers::ReceiverStream;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use self::cache::{Coverage, ReportCache};
+use self::models::{
+    HistoryEvent, HistoryKind, InsertOutcome, MergeOutcome, MergedReport, NewHistoryEvent,
+    NewNoteEdit, NewOutboxEvent, NewReport, NewReportNote, NewReportRow, NewReportTag, NoteEdit,
+    OutboxEvent, OutboxKind, Report, ReportMerge, ReportNote, ReportRow, ReportStatus,
+    ReportTransition, ResolutionOutcome, TransitionOutcome, TransporterRegistration,
+};
+use self::notify::{ChangeListener, ChangeNotification};
+use self::rate_limit::RateBucket;
 
-use self::models::{NewReport, Report};
+embed_migrations!("./migrations");
 
 pub mod report {
     tonic::include_proto!("report");
 }
 
-// Comment ..
+pub mod report1_0 {
+    tonic::include_proto!("report1_0");
+}
+
+///
+/// Attach the tags stored in `report_tags` to report rows.
+///
+fn with_tags(conn: &PgConnection, rows: Vec<ReportRow>) -> QueryResult<Vec<Report>> {
+    use schema::report_tags::dsl::*;
+
+    let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+
+    let loaded = report_tags
+        .filter(report_id.eq_any(ids))
+        .order(tag.asc())
+        .load::<(i64, String)>(conn)?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+
+    for (identifier, value) in loaded {
+        tags.entry(identifier).or_default().push(value);
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let row_tags = tags.remove(&row.id).unwrap_or_default();
+            Report::from_row(row, row_tags)
+        })
+        .collect())
+}
+
+///
+/// Load a single page of reports, along with the token of the next one.
+///
+fn load_page(
+    conn: &PgConnection,
+    filters: &ReportFilterSet,
+    order: ReportOrder,
+    page: &Page,
+) -> QueryResult<ReportPage> {
+    use schema::reports::dsl::*;
+
+    let size = page.size as usize;
+
+    let mut rows = filters
+        .to_page_query(&order, page)
+        .load::<ReportRow>(conn)?;
+
+    if rows.len() <= size {
+        return Ok(ReportPage {
+            reports: with_tags(conn, rows)?,
+            next: None,
+        });
+    }
+
+    rows.truncate(size);
+
+    let res = with_tags(conn, rows)?;
+    let next = res.last().map(|last| PageToken::new(order, last));
+
+    // Ranks only exist in the database, look up the one of the last report.
+    let next = match (next, filters.search()) {
+        (Some(token), Some(text)) if order.key == SortKey::Relevance => {
+            let rank = reports
+                .filter(id.eq(token.id))
+                .select(search::rank(text))
+                .get_result::<f32>(conn)?;
+
+            Some(token.with_rank(rank))
+        }
+        (next, _) => next,
+    };
+
+    Ok(ReportPage { reports: res, next })
+}
 
 ///
-/// Specify type kind of query to execute.
+/// Write the outbox broadcast of a changed report and notify other
+/// instances of the change, both taking effect once the surrounding
+/// transaction commits.
 ///
-pub enum QueryType {
-    ALL,
-    ByReporter(String),
-    ByReported(String),
-    ByTimestamp(i64),
-    ById(i64),
-    ByActive,
-    ByHandler(String),
-    ByHandleTimestamp(i64),
+/// Updates, merges and new priorities are not broadcast, transporters
+/// only knowing whether reports are active.
+///
+fn publish_change(
+    conn: &PgConnection,
+    instance: Uuid,
+    kind: OutboxKind,
+    report: &Report,
+) -> QueryResult<()> {
+    use schema::report_outbox::dsl::report_outbox;
+
+    if !matches!(
+        kind,
+        OutboxKind::Update | OutboxKind::Merge | OutboxKind::Rescore
+    ) {
+        insert_into(report_outbox)
+            .values(NewOutboxEvent::new(kind, report))
+            .execute(conn)?;
+    }
+
+    notify::notify(
+        conn,
+        &ChangeNotification {
+            instance,
+            kind,
+            id: report.id,
+        },
+    )
 }
 
+/// Amount of reports loaded at a time when streaming reports.
+const STREAM_CHUNK_SIZE: i64 = 256;
+
+/// Amount of reports rescored in a transaction when refreshing priorities.
+const PRIORITY_BATCH_SIZE: i64 = 500;
+
+///
+/// Reports streamed from the database, ending early on the first error.
+///
+pub type ReportStream = ReceiverStream<Result<Report, AsyncError>>;
+
 #[tonic::async_trait]
 pub trait ReportDb<M>
 where
     M: diesel::r2d2::ManageConnection,
 {
-    async fn insert_report(&self, new_report: NewReport) -> Result<Report, Box<dyn Error>>;
+    async fn insert_report(
+        &self,
+        new_report: NewReport,
+        policy: Option<DuplicatePolicy>,
+    ) -> Result<InsertOutcome, Box<dyn Error>>;
+
+    async fn merge_reports(&self, merge: ReportMerge) -> Result<MergeOutcome, Box<dyn Error>>;
+
+    async fn take_tokens(
+        &self,
+        buckets: Vec<(String, RateLimit)>,
+    ) -> Result<Option<Duration>, Box<dyn Error>>;
+
+    async fn refund_tokens(&self, buckets: Vec<(String, RateLimit)>) -> Result<(), Box<dyn Error>>;
+
+    async fn prune_rate_limits(&self) -> Result<usize, Box<dyn Error>>;
+
+    async fn refresh_priorities(&self) -> Result<usize, Box<dyn Error>>;
+
+    async fn query_report(
+        &self,
+        filters: ReportFilterSet,
+        order: ReportOrder,
+    ) -> Result<Vec<Report>, Box<dyn Error>>;
 
-    async fn query_report(&self, query_type: QueryType) -> Result<Vec<Report>, Box<dyn Error>>;
+    async fn query_report_page(
+        &self,
+        filters: ReportFilterSet,
+        order: ReportOrder,
+        page: Page,
+    ) -> Result<ReportPage, Box<dyn Error>>;
+
+    fn stream_report(&self, filters: ReportFilterSet, order: ReportOrder) -> ReportStream;
+
+    async fn search_snippets(
+        &self,
+        search: String,
+        ids: Vec<i64>,
+    ) -> Result<HashMap<i64, String>, Box<dyn Error>>;
 
-    async fn deactivate_report(
+    async fn transition_report(
+        &self,
+        transition: ReportTransition,
+        lease: Duration,
+    ) -> Result<TransitionOutcome, Box<dyn Error>>;
+
+    async fn claim_report(
         &self,
         id: i64,
         operator: String,
-        comment: Option<String>,
-    ) -> Result<Report, Box<dyn Error>>;
+        lease: Duration,
+    ) -> Result<TransitionOutcome, Box<dyn Error>>;
+
+    async fn release_expired_claims(&self) -> Result<Vec<Report>, Box<dyn Error>>;
+
+    async fn report_history(&self, id: i64) -> Result<Vec<HistoryEvent>, Box<dyn Error>>;
+
+    async fn add_note(
+        &self,
+        report_id: i64,
+        author: String,
+        body: String,
+    ) -> Result<Option<ReportNote>, Box<dyn Error>>;
+
+    async fn note(&self, id: i64) -> Result<Option<ReportNote>, Box<dyn Error>>;
+
+    async fn edit_note(
+        &self,
+        id: i64,
+        editor: String,
+        body: String,
+    ) -> Result<Option<ReportNote>, Box<dyn Error>>;
+
+    async fn report_notes(&self, report_ids: Vec<i64>) -> Result<Vec<ReportNote>, Box<dyn Error>>;
+
+    async fn note_edits(&self, note_ids: Vec<i64>) -> Result<Vec<NoteEdit>, Box<dyn Error>>;
+
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, Box<dyn Error>>;
+
+    async fn complete_outbox(&self, id: i64) -> Result<(), Box<dyn Error>>;
+
+    async fn retry_outbox(
+        &self,
+        id: i64,
+        delivered_to: Vec<String>,
+        error: String,
+        at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn fail_outbox(
+        &self,
+        id: i64,
+        delivered_to: Vec<String>,
+        error: String,
+    ) -> Result<(), Box<dyn Error>>;
+
+    async fn register_transporter(
+        &self,
+        address: String,
+        node: String,
+        lease: Duration,
+    ) -> Result<TransporterRegistration, Box<dyn Error>>;
+
+    async fn renew_transporter(
+        &self,
+        address: String,
+        lease: Duration,
+    ) -> Result<Option<TransporterRegistration>, Box<dyn Error>>;
+
+    async fn live_transporters(&self) -> Result<Vec<TransporterRegistration>, Box<dyn Error>>;
+}
+
+/// Time a claimed outbox event is hidden from other dispatchers while delivered.
+pub const OUTBOX_LEASE_SECS: i64 = 60;
+
+/// Attempts after which an outbox event is left undelivered as a dead letter.
+pub const OUTBOX_MAX_ATTEMPTS: i32 = 12;
+
+///
+/// Reports kept in the in-memory cache.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheMode {
+    /// Every query reads the database.
+    Disabled,
+    /// Reports are cached as they are read, only answering lookups by id.
+    Partial,
+    /// Active reports are loaded on startup, answering queries for active reports.
+    Active,
+    /// Every report is loaded on startup, answering any query.
+    All,
+}
+
+impl FromStr for CacheMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(CacheMode::Disabled),
+            "partial" => Ok(CacheMode::Partial),
+            "active" => Ok(CacheMode::Active),
+            "all" => Ok(CacheMode::All),
+            _ => Err("invalid cache mode"),
+        }
+    }
+}
+
+///
+/// When a submitted report counts as a duplicate of an active report of
+/// the same reporter against the same player.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuplicatePolicy {
+    /// How long after the active report.
+    pub window: Duration,
+    /// Least similarity of the descriptions, see
+    /// `models::description_similarity`. `None` ignores descriptions.
+    pub similarity: Option<f64>,
+}
+
+impl DuplicatePolicy {
+    pub fn similar(&self, a: &str, b: &str) -> bool {
+        match self.similarity {
+            Some(least) => models::description_similarity(a, b) >= least,
+            None => true,
+        }
+    }
+}
+
+///
+/// The most recent active report of a reporter against a player since
+/// `since` which a new report duplicates under `policy`, locked for update.
+///
+/// Submits of the same reporter against the same player are serialized
+/// until the surrounding transaction ends, so concurrent duplicates find
+/// each other.
+///
+fn find_original(
+    conn: &PgConnection,
+    new_report: &NewReportRow,
+    policy: &DuplicatePolicy,
+    since: DateTime<Utc>,
+) -> QueryResult<Option<ReportRow>> {
+    use diesel::sql_types::Text;
+    use schema::reports::dsl::*;
+
+    diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind::<Text, _>(format!("{}\n{}", new_report.reporter, new_report.reported))
+        .execute(conn)?;
+
+    let candidates = reports
+        .filter(reporter.eq(&new_report.reporter))
+        .filter(reported.eq(&new_report.reported))
+        .filter(active.eq(true))
+        .filter(parent_id.is_null())
+        .filter(timestamp.ge(since))
+        .order(timestamp.desc())
+        .for_update()
+        .load::<ReportRow>(conn)?;
+
+    Ok(candidates
+        .into_iter()
+        .find(|row| policy.similar(&row.description, &new_report.description)))
+}
+
+///
+/// Score report rows at `at`, storing the priorities which changed and
+/// returning the reports whose priority changed.
+///
+fn rescore(
+    conn: &PgConnection,
+    rows: Vec<ReportRow>,
+    weights: &PriorityWeights,
+    at: DateTime<Utc>,
+) -> QueryResult<Vec<Report>> {
+    use schema::reports::dsl::*;
+
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let players: HashSet<String> = rows.iter().map(|row| row.reported.clone()).collect();
+    let reporters: HashSet<String> = rows.iter().map(|row| row.reporter.clone()).collect();
+
+    let counts =
+        crate::priority::reporter_counts(conn, players.into_iter().collect(), at - weights.window)?;
+    let records = crate::priority::reporter_records(conn, reporters.into_iter().collect())?;
+
+    let mut changed = Vec::new();
+
+    for mut report in with_tags(conn, rows)? {
+        let score = weights.score(
+            &report,
+            counts.get(&report.reported).copied().unwrap_or(1),
+            &records.get(&report.reporter).copied().unwrap_or_default(),
+            at,
+        );
+
+        if score != report.priority {
+            update(reports.filter(id.eq(report.id)))
+                .set(priority.eq(score))
+                .execute(conn)?;
+
+            report.priority = score;
+            changed.push(report);
+        }
+    }
+
+    Ok(changed)
+}
+
+///
+/// Rescore active report rows, notifying other instances of the new
+/// priorities of every report but `except`.
+///
+/// Rows are loaded skipping the ones locked by other transactions, which
+/// are left to the transaction holding them or to the next refresh.
+///
+fn rescore_related(
+    conn: &PgConnection,
+    instance: Uuid,
+    rows: Vec<ReportRow>,
+    except: i64,
+    weights: &PriorityWeights,
+    at: DateTime<Utc>,
+) -> QueryResult<Vec<Report>> {
+    let changed = rescore(conn, rows, weights, at)?;
+
+    for report in changed.iter().filter(|report| report.id != except) {
+        publish_change(conn, instance, OutboxKind::Rescore, report)?;
+    }
+
+    Ok(changed)
 }
 
 pub struct PgReportDb {
+    addr: String,
+    /// Tells apart the changes made by this instance from the ones of others.
+    instance: Uuid,
     pool: diesel::r2d2::Pool<ConnectionManager<PgConnection>>,
-    cache: Arc<RwLock<HashMap<i64, Report>>>,
+    cache: Arc<Mutex<ReportCache>>,
+    cache_mode: CacheMode,
+    cache_capacity: usize,
+    priority: PriorityWeights,
 }
 
 impl PgReportDb {
-    pub fn new(addr: &str) -> Result<Self, Box<dyn Error>> {
+    ///
+    /// Connect a pool of at most `pool_size` connections, waiting up to
+    /// `timeout` for a connection to be checked out.
+    ///
+    /// At most `cache_capacity` reports are cached, and reports are
+    /// scored under `priority`.
+    ///
+    pub fn new(
+        addr: &str,
+        pool_size: u32,
+        timeout: Duration,
+        cache_mode: CacheMode,
+        cache_capacity: usize,
+        priority: PriorityWeights,
+    ) -> Result<Self, Box<dyn Error>> {
         let manager = ConnectionManager::<PgConnection>::new(addr);
-        let pool = diesel::r2d2::Pool::builder().build(manager)?;
+        let pool = diesel::r2d2::Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(timeout)
+            .build(manager)?;
 
         Ok(Self {
+            addr: addr.to_owned(),
+            instance: Uuid::new_v4(),
             pool,
-            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(ReportCache::new(cache_capacity))),
+            cache_mode,
+            cache_capacity,
+            priority,
         })
     }
 
-    pub async fn load_to_cache(&self, deactive: bool) -> Result<(), Box<dyn Error>> {
+    ///
+    /// Load the reports of the cache mode to the cache, returning the
+    /// coverage reached.
+    ///
+    /// The coverage falls to `Coverage::Partial` when the reports do not
+    /// fit in the cache.
+    ///
+    pub async fn warm_cache(&self) -> Result<Coverage, Box<dyn Error>> {
+        match self.cache_mode {
+            CacheMode::Disabled | CacheMode::Partial => Ok(Coverage::Partial),
+            CacheMode::Active => self.load_to_cache(false).await,
+            CacheMode::All => self.load_to_cache(true).await,
+        }
+    }
+
+    ///
+    /// Run pending database migrations.
+    ///
+    pub fn run_migrations(&self) -> Result<(), Box<dyn Error>> {
+        embedded_migrations::run(&self.pool.get()?)?;
+
+        Ok(())
+    }
+
+    pub async fn load_to_cache(&self, deactive: bool) -> Result<Coverage, Box<dyn Error>> {
         use schema::reports::dsl::*;
 
-        let to_cache: Vec<Report>;
+        let capacity = self.cache_capacity;
 
-        if deactive {
-            to_cache = reports.load(&self.pool.get()?)?;
-        } else {
+        // Boxed queries are not `Send`, so build the query on the pooled connection.
+        let (to_cache, coverage) = self
+            .pool
+            .run(move |conn| {
+                let mut query = reports.into_boxed();
 
-            to_cache = reports
-                .filter(active.eq(true))
-                .load::<Report>(&self.pool.get()?)?;
-        }
+                if !deactive {
+                    query = query.filter(active.eq(true));
+                }
+
+                // Loading one report more than fits tells whether every one does.
+                let mut rows = query
+                    .order(id.desc())
+                    .limit(capacity as i64 + 1)
+                    .load::<ReportRow>(conn)?;
+
+                let coverage = if rows.len() > capacity {
+                    rows.truncate(capacity);
+                    Coverage::Partial
+                } else if deactive {
+                    Coverage::All
+                } else {
+                    Coverage::Active
+                };
+
+                // Oldest first, so the newest count as most recently used.
+                rows.reverse();
+
+                Ok((with_tags(conn, rows)?, coverage))
+            })
+            .await?;
+
+        self.cache.lock().await.warm(coverage, to_cache);
+
+        Ok(coverage)
+    }
+
+    ///
+    /// Drop every cached report.
+    ///
+    pub async fn clear_cache(&self) {
+        self.cache.lock().await.clear();
+    }
+
+    ///
+    /// Listen for report changes made by every instance sharing the database.
+    ///
+    pub async fn listen(&self) -> Result<ChangeListener, Box<dyn Error>> {
+        Ok(ChangeListener::connect(&self.addr).await?)
+    }
 
-        for report in to_cache {
-            self.cache.write().await.insert(report.id, report);
+    ///
+    /// Whether a change was made by this instance.
+    ///
+    pub fn is_local(&self, change: &ChangeNotification) -> bool {
+        change.instance == self.instance
+    }
+
+    ///
+    /// Reload a changed report into the cache.
+    ///
+    /// Changes of this instance are reloaded as well, as a concurrent
+    /// warm up may have cached an older state. `None` when the report no
+    /// longer exists.
+    ///
+    pub async fn apply_change(
+        &self,
+        change: ChangeNotification,
+    ) -> Result<Option<Report>, Box<dyn Error>> {
+        use schema::reports::dsl::*;
+
+        let res = self
+            .pool
+            .run(move |conn| {
+                let rows = reports.filter(id.eq(change.id)).load::<ReportRow>(conn)?;
+
+                with_tags(conn, rows)
+            })
+            .await?
+            .pop();
+
+        match &res {
+            Some(report) => self.insert_to_cache(report.clone()).await,
+            None => {
+                self.cache.lock().await.remove(change.id);
+            }
         }
 
-        Ok(())
+        Ok(res)
     }
 
     async fn insert_to_cache(&self, insertee: Report) {
-        let mut lock = self.cache.write().await;
-        lock.insert(insertee.id, insertee);
+        if self.cache_mode == CacheMode::Disabled {
+            return;
+        }
+
+        self.cache.lock().await.put(insertee);
     }
 }
 
 #[tonic::async_trait]
 impl ReportDb<ConnectionManager<PgConnection>> for PgReportDb {
-    async fn insert_report(&self, new_report: NewReport) -> Result<Report, Box<dyn Error>> {
+    ///
+    /// Insert a report along with its tags, history and outbox broadcast,
+    /// notifying other instances.
+    ///
+    /// Reports duplicating an active report under `policy` are inserted
+    /// as its duplicates instead, counted by it and not broadcast.
+    ///
+    async fn insert_report(
+        &self,
+        new_report: NewReport,
+        policy: Option<DuplicatePolicy>,
+    ) -> Result<InsertOutcome, Box<dyn Error>> {
+        use schema::report_events::dsl::report_events;
+        use schema::report_tags::dsl::report_tags;
         use schema::reports::dsl::*;
 
-        // let rep = new_report.clone();
+        let (mut row, tags) = new_report.into_parts();
+        let instance = self.instance;
+        let weights = self.priority.clone();
 
-        let res = insert_into(reports)
-            .values(new_report)
-            .get_result_async::<Report>(&self.pool).await?;
+        let since = match &policy {
+            Some(policy) => Some(row.timestamp - chrono::Duration::from_std(policy.window)?),
+            None => None,
+        };
 
-        self.insert_to_cache(res.clone()).await;
+        let (res, rescored) = self
+            .pool
+            .transaction(move |conn| {
+                let original = match (&policy, since) {
+                    (Some(policy), Some(since)) => find_original(conn, &row, policy, since)?,
+                    _ => None,
+                };
 
-        Ok(res)
-    }
+                if let Some(original) = &original {
+                    row.status = ReportStatus::Duplicate.as_str().to_owned();
+                    row.outcome = Some(ResolutionOutcome::Duplicate.as_str().to_owned());
+                    row.parent_id = Some(original.id);
+                }
 
-    async fn deactivate_report(
-        &self,
-        identifier: i64,
-        operator: String,
-        ccomment: Option<String>,
-    ) -> Result<Report, Box<dyn Error>> {
-        use schema::reports::dsl::*;
+                let inserted = insert_into(reports)
+                    .values(row)
+                    .get_result::<ReportRow>(conn)?;
 
-        let utc = chrono::Utc::now();
-        let ts = utc.timestamp();
+                let new_tags: Vec<NewReportTag> = tags
+                    .iter()
+                    .map(|tag| NewReportTag {
+                        report_id: inserted.id,
+                        tag: tag.clone(),
+                    })
+                    .collect();
 
-        update(reports.filter(id.eq(identifier)))
-            .set(active.eq(false))
-            .execute_async(&self.pool).await?;
+                insert_into(report_tags).values(&new_tags).execute(conn)?;
 
-        update(reports.filter(id.eq(identifier)))
-            .set(handler.eq(operator))
-            .execute_async(&self.pool).await?;
+                let mut report = Report::from_row(inserted, tags);
 
-        if let Some(comm) = ccomment {
-            update(reports.filter(id.eq(identifier)))
-                .set(comment.eq(comm))
-                .execute_async(&self.pool).await?;
-        }
+                insert_into(report_events)
+                    .values(NewHistoryEvent::insert(&report))
+                    .execute(conn)?;
+
+                let before = match original {
+                    Some(val) => val,
+                    None => {
+                        // Another reporter may raise the priority of every
+                        // report against the player.
+                        let related = reports
+                            .filter(active.eq(true))
+                            .filter(reported.eq(&report.reported))
+                            .order(id.asc())
+                            .for_update()
+                            .skip_locked()
+                            .load::<ReportRow>(conn)?;
+
+                        let mut rescored = rescore_related(
+                            conn,
+                            instance,
+                            related,
+                            report.id,
+                            &weights,
+                            report.timestamp,
+                        )?;
 
-        let res = update(reports.filter(id.eq(identifier)))
-            .set(handle_ts.eq(ts))
-            .get_result_async::<Report>(&self.pool).await?;
+                        if let Some(at) = rescored.iter().position(|val| val.id == report.id) {
+                            report = rescored.swap_remove(at);
+                        }
 
-        self.insert_to_cache(res.clone()).await;
+                        publish_change(conn, instance, OutboxKind::Insert, &report)?;
+
+                        return Ok((InsertOutcome::Inserted(Box::new(report)), rescored));
+                    }
+                };
+
+                let after = update(reports.filter(id.eq(before.id)))
+                    .set(duplicates.eq(duplicates + 1))
+                    .get_result::<ReportRow>(conn)?;
+
+                let event = NewHistoryEvent::change(
+                    HistoryKind::Merge,
+                    Some(report.reporter.clone()),
+                    report.timestamp,
+                    &before,
+                    &after,
+                    &["duplicates"],
+                )
+                .map(|event| event.with_reason(Some(format!("duplicate report {}", report.id))));
+
+                if let Some(event) = event {
+                    insert_into(report_events).values(event).execute(conn)?;
+                }
+
+                let parent = with_tags(conn, vec![after])?.remove(0);
+
+                publish_change(conn, instance, OutboxKind::Merge, &report)?;
+                publish_change(conn, instance, OutboxKind::Update, &parent)?;
+
+                let merged = MergedReport { report, parent };
+
+                Ok((InsertOutcome::Duplicate(Box::new(merged)), Vec::new()))
+            })
+            .await?;
+
+        for report in rescored {
+            self.insert_to_cache(report).await;
+        }
+
+        match &res {
+            InsertOutcome::Inserted(report) => self.insert_to_cache(report.as_ref().clone()).await,
+            InsertOutcome::Duplicate(merged) => {
+                self.insert_to_cache(merged.report.clone()).await;
+                self.insert_to_cache(merged.parent.clone()).await;
+            }
+        }
 
         Ok(res)
     }
 
     ///
-    /// Query reports by a query type.
-    ///
-    /// # Arguments
-    ///
-    /// * `query_type` - `QueryType` enum with a required value.
+    /// Merge an active report into another as a duplicate, closing it and
+    /// writing its history and outbox broadcast, notifying other instances.
     ///
+    /// Reports are merged into the report the one merged into duplicates,
+    /// which counts the merged report along with its own duplicates. The
+    /// duplicates of the merged report stay linked to it.
     ///
-    async fn query_report(&self, query_type: QueryType) -> Result<Vec<Report>, Box<dyn Error>> {
+    async fn merge_reports(&self, merge: ReportMerge) -> Result<MergeOutcome, Box<dyn Error>> {
+        use schema::report_events::dsl::report_events;
+        use schema::report_outbox::dsl::report_outbox;
         use schema::reports::dsl::*;
 
-        let res: Vec<Report>;
+        let ts = models::now();
+        let instance = self.instance;
 
-        match query_type {
-            QueryType::ALL => {
-                let cached: Vec<Report> = self.cache.read().await.values().cloned().collect();
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                let mut rows = reports
+                    .filter(id.eq_any(vec![merge.id, merge.into]))
+                    .order(id.asc())
+                    .for_update()
+                    .load::<ReportRow>(conn)?;
 
-                if cached.is_empty() {
-                    res = reports.load_async(&self.pool).await?;
-                } else {
-                    res = cached;
+                if merge.id == merge.into {
+                    return Ok(MergeOutcome::IntoItself);
                 }
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
+                if rows.len() < 2 {
+                    return Ok(MergeOutcome::NotFound);
                 }
-            }
-            QueryType::ByReporter(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.reporter == value)
-                    .collect();
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(reporter.eq(value))
-                        .load_async::<Report>(&self.pool).await?;
+                let (before, mut parent_before) = if rows[0].id == merge.id {
+                    let target = rows.remove(1);
+                    (rows.remove(0), target)
                 } else {
-                    res = cached;
-                }
+                    let target = rows.remove(0);
+                    (rows.remove(0), target)
+                };
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
-                }
-            }
-            QueryType::ByReported(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.reported == value)
-                    .collect();
+                while let Some(parent) = parent_before.parent_id {
+                    if parent == before.id {
+                        return Ok(MergeOutcome::IntoItself);
+                    }
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(reported.eq(value))
-                        .load_async::<Report>(&self.pool).await?;
-                } else {
-                    res = cached;
+                    parent_before = reports
+                        .filter(id.eq(parent))
+                        .for_update()
+                        .get_result::<ReportRow>(conn)?;
                 }
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
+                match before.live_claimant(ts) {
+                    Some(holder) if holder != merge.operator && !merge.force => {
+                        return Ok(MergeOutcome::Held(holder.to_owned()))
+                    }
+                    _ => {}
                 }
-            }
-            QueryType::ByTimestamp(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.timestamp <= value)
-                    .collect();
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(timestamp.le(value))
-                        .load_async::<Report>(&self.pool).await?;
-                } else {
-                    res = cached;
-                }
+                let from = before.status_at(ts);
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
+                if !from.can_transition_to(ReportStatus::Duplicate) {
+                    return Ok(MergeOutcome::Illegal(from));
                 }
-            }
-            QueryType::ById(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.id == value)
-                    .collect();
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(id.eq(value))
-                        .load_async::<Report>(&self.pool).await?;
-                } else {
-                    res = cached;
-                }
+                let row = update(reports.filter(id.eq(before.id)))
+                    .set((
+                        status.eq(ReportStatus::Duplicate.as_str()),
+                        claimant.eq(None::<String>),
+                        claim_expires.eq(None::<DateTime<Utc>>),
+                        handler.eq(&merge.operator),
+                        handle_ts.eq(ts),
+                        outcome.eq(ResolutionOutcome::Duplicate.as_str()),
+                        parent_id.eq(parent_before.id),
+                    ))
+                    .get_result::<ReportRow>(conn)?;
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
-                }
-            }
-            QueryType::ByActive => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.active)
-                    .collect();
+                let parent_after = update(reports.filter(id.eq(parent_before.id)))
+                    .set(duplicates.eq(duplicates + before.duplicates + 1))
+                    .get_result::<ReportRow>(conn)?;
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(active.eq(true))
-                        .load_async::<Report>(&self.pool).await?
-                } else {
-                    res = cached;
-                }
+                let actor = Some(merge.operator);
+                let reason = merge.reason;
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
-                }
-            }
-            QueryType::ByHandler(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.handler == Some(value.clone()))
-                    .collect();
+                let events: Vec<NewHistoryEvent> = vec![
+                    NewHistoryEvent::change(
+                        HistoryKind::Merge,
+                        actor.clone(),
+                        ts,
+                        &before,
+                        &row,
+                        &[
+                            "status",
+                            "active",
+                            "claimant",
+                            "handler",
+                            "handle_ts",
+                            "outcome",
+                            "parent_id",
+                        ],
+                    ),
+                    NewHistoryEvent::change(
+                        HistoryKind::Merge,
+                        actor,
+                        ts,
+                        &parent_before,
+                        &parent_after,
+                        &["duplicates"],
+                    ),
+                ]
+                .into_iter()
+                .flatten()
+                .map(|event| event.with_reason(reason.clone()))
+                .collect();
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(handler.eq(value))
-                        .load_async::<Report>(&self.pool).await?;
-                } else {
-                    res = cached;
-                }
+                insert_into(report_events).values(&events).execute(conn)?;
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
-                }
-            }
-            QueryType::ByHandleTimestamp(value) => {
-                let cached: Vec<Report> = self
-                    .cache
-                    .read()
-                    .await
-                    .values()
-                    .cloned()
-                    .filter(|x| x.handle_ts <= Some(value))
-                    .collect();
+                let mut merged = with_tags(conn, vec![row, parent_after])?;
+                let parent = merged.remove(1);
+                let report = merged.remove(0);
 
-                if cached.is_empty() {
-                    res = reports
-                        .filter(handle_ts.eq(value))
-                        .load_async::<Report>(&self.pool).await?;
-                } else {
-                    res = cached;
-                }
+                // Transporters only know of the report closing.
+                insert_into(report_outbox)
+                    .values(NewOutboxEvent::new(OutboxKind::Deactivate, &report))
+                    .execute(conn)?;
 
-                for report in &res {
-                    self.insert_to_cache(report.clone()).await;
-                }
-            }
+                publish_change(conn, instance, OutboxKind::Merge, &report)?;
+                publish_change(conn, instance, OutboxKind::Update, &parent)?;
+
+                Ok(MergeOutcome::Merged(Box::new(MergedReport {
+                    report,
+                    parent,
+                })))
+            })
+            .await?;
+
+        if let MergeOutcome::Merged(merged) = &res {
+            self.insert_to_cache(merged.report.clone()).await;
+            self.insert_to_cache(merged.parent.clone()).await;
         }
 
         Ok(res)
     }
+
+    ///
+    /// Move a report to another status and write its history and outbox
+    /// broadcast, notifying other instances.
+    ///
+    /// Closing a report hands it to the operator with the outcome of the
+    /// transition. A missing comment keeps the current one, while a changed
+    /// comment is recorded as a history event of its own.
+    ///
+    /// Reopening a report clears its handling and outcome unless
+    /// `keep_handling`.
+    ///
+    /// Claimed and in review reports are claimed by the operator for
+    /// `lease`, other reports are not claimed by anyone.
+    ///
+    /// The move is checked against the status the report is locked in,
+    /// and reports claimed by another handler only move when forced.
+    ///
+    async fn transition_report(
+        &self,
+        transition: ReportTransition,
+        lease: Duration,
+    ) -> Result<TransitionOutcome, Box<dyn Error>> {
+        use schema::report_events::dsl::report_events;
+        use schema::reports::dsl::*;
+
+        let ts = models::now();
+        let until = ts + chrono::Duration::from_std(lease)?;
+        let instance = self.instance;
+        let weights = self.priority.clone();
+
+        let (res, rescored) = self
+            .pool
+            .transaction(move |conn| {
+                let target = reports.filter(id.eq(transition.id));
+                let before = match target
+                    .for_update()
+                    .get_result::<ReportRow>(conn)
+                    .optional()?
+                {
+                    Some(val) => val,
+                    None => return Ok((TransitionOutcome::NotFound, Vec::new())),
+                };
+
+                match before.live_claimant(ts) {
+                    Some(holder) if holder != transition.operator && !transition.force => {
+                        return Ok((TransitionOutcome::Held(holder.to_owned()), Vec::new()))
+                    }
+                    _ => {}
+                }
+
+                let from = before.status_at(ts);
+                let to = transition.status;
+
+                if !from.can_transition_to(to) {
+                    return Ok((TransitionOutcome::Illegal(from), Vec::new()));
+                }
+
+                let kind = if !to.is_active() {
+                    OutboxKind::Deactivate
+                } else if !from.is_active() {
+                    OutboxKind::Reactivate
+                } else {
+                    OutboxKind::Update
+                };
+
+                let (holder, expires) = match to {
+                    ReportStatus::Claimed | ReportStatus::InReview => {
+                        (Some(transition.operator.clone()), Some(until))
+                    }
+                    _ => (None, None),
+                };
+
+                let row = match kind {
+                    OutboxKind::Deactivate => update(target)
+                        .set((
+                            status.eq(to.as_str()),
+                            claimant.eq(holder),
+                            claim_expires.eq(expires),
+                            handler.eq(&transition.operator),
+                            handle_ts.eq(ts),
+                            comment.eq(transition.comment.or_else(|| before.comment.clone())),
+                            outcome.eq(transition.outcome.map(|value| value.as_str())),
+                        ))
+                        .get_result::<ReportRow>(conn)?,
+                    OutboxKind::Reactivate if !transition.keep_handling => update(target)
+                        .set((
+                            status.eq(to.as_str()),
+                            claimant.eq(holder),
+                            claim_expires.eq(expires),
+                            handler.eq(None::<String>),
+                            handle_ts.eq(None::<DateTime<Utc>>),
+                            comment.eq(None::<String>),
+                            outcome.eq(None::<String>),
+                        ))
+                        .get_result::<ReportRow>(conn)?,
+                    _ => update(target)
+                        .set((
+                            status.eq(to.as_str()),
+                            claimant.eq(holder),
+                            claim_expires.eq(expires),
+                        ))
+                        .get_result::<ReportRow>(conn)?,
+                };
+
+                let actor = Some(transition.operator);
+                let reason = transition.reason;
+
+                let (history, fields): (HistoryKind, &[&str]) = match kind {
+                    OutboxKind::Deactivate => (
+                        HistoryKind::Deactivate,
+                        &[
+                            "status",
+                            "active",
+                            "claimant",
+                            "handler",
+                            "handle_ts",
+                            "outcome",
+                        ],
+                    ),
+                    OutboxKind::Reactivate => (
+                        HistoryKind::Reopen,
+                        &[
+                            "status",
+                            "active",
+                            "handler",
+                            "handle_ts",
+                            "comment",
+                            "outcome",
+                        ],
+                    ),
+                    _ if before.claimant != row.claimant => {
+                        (HistoryKind::Assign, &["status", "claimant"])
+                    }
+                    _ => (HistoryKind::Status, &["status"]),
+                };
+
+                let mut events: Vec<NewHistoryEvent> =
+                    NewHistoryEvent::change(history, actor.clone(), ts, &before, &row, fields)
+                        .map(|event| event.with_reason(reason))
+                        .into_iter()
+                        .collect();
+
+                if kind == OutboxKind::Deactivate {
+                    events.extend(NewHistoryEvent::change(
+                        HistoryKind::Comment,
+                        actor,
+                        ts,
+                        &before,
+                        &row,
+                        &["comment"],
+                    ));
+                }
+
+                insert_into(report_events).values(&events).execute(conn)?;
+
+                let mut report = with_tags(conn, vec![row])?.remove(0);
+
+                // Closing or reopening changes the accuracy of the reporter,
+                // and reopened reports are waiting again.
+                let mut rescored = match kind {
+                    OutboxKind::Deactivate | OutboxKind::Reactivate => {
+                        let related = reports
+                            .filter(active.eq(true))
+                            .filter(reporter.eq(&report.reporter))
+                            .order(id.asc())
+                            .for_update()
+                            .skip_locked()
+                            .load::<ReportRow>(conn)?;
+
+                        rescore_related(conn, instance, related, report.id, &weights, ts)?
+                    }
+                    _ => Vec::new(),
+                };
+
+                if let Some(at) = rescored.iter().position(|val| val.id == report.id) {
+                    report = rescored.swap_remove(at);
+                }
+
+                publish_change(conn, instance, kind, &report)?;
+
+                Ok((TransitionOutcome::Moved(Box::new(report), kind), rescored))
+            })
+            .await?;
+
+        for report in rescored {
+            self.insert_to_cache(report).await;
+        }
+
+        if let TransitionOutcome::Moved(report, _) = &res {
+            self.insert_to_cache(report.as_ref().clone()).await;
+        }
+
+        Ok(res)
+    }
+
+    ///
+    /// Claim an active report for `lease`, or extend the claim of the
+    /// operator, notifying other instances.
+    ///
+    /// Open reports become claimed, while claimed and in review reports
+    /// keep their status.
+    ///
+    async fn claim_report(
+        &self,
+        identifier: i64,
+        operator: String,
+        lease: Duration,
+    ) -> Result<TransitionOutcome, Box<dyn Error>> {
+        use schema::report_events::dsl::report_events;
+        use schema::reports::dsl::*;
+
+        let ts = models::now();
+        let until = ts + chrono::Duration::from_std(lease)?;
+        let instance = self.instance;
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                let target = reports.filter(id.eq(identifier));
+                let before = match target
+                    .for_update()
+                    .get_result::<ReportRow>(conn)
+                    .optional()?
+                {
+                    Some(val) => val,
+                    None => return Ok(TransitionOutcome::NotFound),
+                };
+
+                match before.live_claimant(ts) {
+                    Some(holder) if holder != operator => {
+                        return Ok(TransitionOutcome::Held(holder.to_owned()))
+                    }
+                    _ => {}
+                }
+
+                let to = match before.status_at(ts) {
+                    ReportStatus::Open => ReportStatus::Claimed,
+                    from if from.is_active() => from,
+                    from => return Ok(TransitionOutcome::Illegal(from)),
+                };
+
+                let row = update(target)
+                    .set((
+                        status.eq(to.as_str()),
+                        claimant.eq(&operator),
+                        claim_expires.eq(until),
+                    ))
+                    .get_result::<ReportRow>(conn)?;
+
+                let event = NewHistoryEvent::change(
+                    HistoryKind::Assign,
+                    Some(operator),
+                    ts,
+                    &before,
+                    &row,
+                    &["status", "claimant"],
+                );
+
+                if let Some(event) = event {
+                    insert_into(report_events).values(event).execute(conn)?;
+                }
+
+                let report = with_tags(conn, vec![row])?.remove(0);
+
+                publish_change(conn, instance, OutboxKind::Update, &report)?;
+
+                Ok(TransitionOutcome::Moved(
+                    Box::new(report),
+                    OutboxKind::Update,
+                ))
+            })
+            .await?;
+
+        if let TransitionOutcome::Moved(report, _) = &res {
+            self.insert_to_cache(report.as_ref().clone()).await;
+        }
+
+        Ok(res)
+    }
+
+    ///
+    /// Reopen claimed and in review reports whose claim ran out,
+    /// notifying other instances.
+    ///
+    /// Reports locked by a concurrent change are left to the next call.
+    ///
+    async fn release_expired_claims(&self) -> Result<Vec<Report>, Box<dyn Error>> {
+        use schema::report_events::dsl::report_events;
+        use schema::reports::dsl::*;
+
+        let ts = models::now();
+        let instance = self.instance;
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                let expired = reports
+                    .filter(status.eq_any(vec![
+                        ReportStatus::Claimed.as_str(),
+                        ReportStatus::InReview.as_str(),
+                    ]))
+                    .filter(claim_expires.is_null().or(claim_expires.le(ts)))
+                    .for_update()
+                    .skip_locked()
+                    .load::<ReportRow>(conn)?;
+
+                let mut released = Vec::new();
+
+                for before in expired {
+                    let row = update(reports.filter(id.eq(before.id)))
+                        .set((
+                            status.eq(ReportStatus::Open.as_str()),
+                            claimant.eq(None::<String>),
+                            claim_expires.eq(None::<DateTime<Utc>>),
+                        ))
+                        .get_result::<ReportRow>(conn)?;
+
+                    let event = NewHistoryEvent::change(
+                        HistoryKind::Assign,
+                        None,
+                        ts,
+                        &before,
+                        &row,
+                        &["status", "claimant"],
+                    );
+
+                    if let Some(event) = event {
+                        insert_into(report_events)
+                            .values(event.with_reason(Some("claim expired".to_owned())))
+                            .execute(conn)?;
+                    }
+
+                    let report = with_tags(conn, vec![row])?.remove(0);
+
+                    publish_change(conn, instance, OutboxKind::Update, &report)?;
+
+                    released.push(report);
+                }
+
+                Ok(released)
+            })
+            .await?;
+
+        for report in &res {
+            self.insert_to_cache(report.clone()).await;
+        }
+
+        Ok(res)
+    }
+
+    ///
+    /// Query reports matching every filter of a filter set.
+    ///
+    /// The cache answers when it holds every matching report, the
+    /// database otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `filters` - `ReportFilterSet` compiled into a single query.
+    /// * `order` - `ReportOrder` the reports are sorted in.
+    ///
+    ///
+    async fn query_report(
+        &self,
+        filters: ReportFilterSet,
+        order: ReportOrder,
+    ) -> Result<Vec<Report>, Box<dyn Error>> {
+        let cached = self.cache.lock().await.query(&filters);
+
+        if let Some(mut cached) = cached {
+            cached.sort_by(|a, b| order.compare(a, b));
+            return Ok(cached);
+        }
+
+        // Boxed queries are not `Send`, so build the query on the pooled connection.
+        let res = self
+            .pool
+            .run(move |conn| {
+                let rows = filters.to_sorted_query(&order).load::<ReportRow>(conn)?;
+
+                with_tags(conn, rows)
+            })
+            .await?;
+
+        for report in &res {
+            self.insert_to_cache(report.clone()).await;
+        }
+
+        Ok(res)
+    }
+
+    ///
+    /// Query a single page of reports matching every filter of a filter set.
+    ///
+    /// Pages are always read from the database, ties in the sort key
+    /// being broken by id for a stable order.
+    ///
+    async fn query_report_page(
+        &self,
+        filters: ReportFilterSet,
+        order: ReportOrder,
+        page: Page,
+    ) -> Result<ReportPage, Box<dyn Error>> {
+        let res = self
+            .pool
+            .run(move |conn| load_page(conn, &filters, order, &page))
+            .await?;
+
+        for report in &res.reports {
+            self.insert_to_cache(report.clone()).await;
+        }
+
+        Ok(res)
+    }
+
+    ///
+    /// Stream reports matching every filter of a filter set.
+    ///
+    /// Reports are loaded in pages of `STREAM_CHUNK_SIZE`, each on its own
+    /// connection checkout, so slow clients hold no connection or
+    /// transaction while the next page waits to be sent. Loading stops as
+    /// soon as the returned stream is dropped.
+    ///
+    fn stream_report(&self, filters: ReportFilterSet, order: ReportOrder) -> ReportStream {
+        let (tx, rx) = mpsc::channel(STREAM_CHUNK_SIZE as usize);
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut page = Page {
+                size: STREAM_CHUNK_SIZE,
+                after: None,
+            };
+
+            loop {
+                let loaded = {
+                    let filters = filters.clone();
+                    let page = page.clone();
+
+                    pool.run(move |conn| load_page(conn, &filters, order, &page))
+                        .await
+                };
+
+                let loaded = match loaded {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+                for report in loaded.reports {
+                    if tx.send(Ok(report)).await.is_err() {
+                        // Receiver dropped, the client is gone.
+                        return;
+                    }
+                }
+
+                match loaded.next {
+                    Some(token) => page.after = Some(token),
+                    None => return,
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+
+    ///
+    /// Highlight the matches of a search query in the description and
+    /// comment of reports.
+    ///
+    async fn search_snippets(
+        &self,
+        text: String,
+        ids: Vec<i64>,
+    ) -> Result<HashMap<i64, String>, Box<dyn Error>> {
+        let res = self
+            .pool
+            .run(move |conn| search::snippets(conn, &text, ids))
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// History of a report, oldest change first.
+    ///
+    async fn report_history(&self, identifier: i64) -> Result<Vec<HistoryEvent>, Box<dyn Error>> {
+        use schema::report_events::dsl::*;
+
+        let res = report_events
+            .filter(report_id.eq(identifier))
+            .order((timestamp.asc(), id.asc()))
+            .load_async::<HistoryEvent>(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Take a token of every bucket, each limited by its rate limit, or
+    /// none of them when one is empty.
+    ///
+    /// `None` when the tokens were taken, otherwise the time until every
+    /// bucket holds a token again.
+    ///
+    async fn take_tokens(
+        &self,
+        buckets: Vec<(String, RateLimit)>,
+    ) -> Result<Option<Duration>, Box<dyn Error>> {
+        use schema::rate_limits::dsl::*;
+
+        let ts = models::now();
+
+        let limits: HashMap<String, RateLimit> = buckets.into_iter().collect();
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                let fresh: Vec<RateBucket> = limits
+                    .iter()
+                    .map(|(name, limit)| RateBucket::full(name.clone(), limit, ts))
+                    .collect();
+
+                insert_into(rate_limits)
+                    .values(&fresh)
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+
+                // Locked in order so concurrent submits cannot deadlock.
+                let locked = rate_limits
+                    .filter(bucket.eq_any(limits.keys()))
+                    .order(bucket.asc())
+                    .for_update()
+                    .load::<RateBucket>(conn)?;
+
+                let mut retry: Option<Duration> = None;
+                let mut taken = Vec::new();
+
+                for row in locked {
+                    let limit = &limits[&row.bucket];
+                    let left = limit.refill(row.tokens, row.updated, ts);
+
+                    if left < 1.0 {
+                        retry = retry.max(Some(limit.retry_after(left)));
+                    } else {
+                        taken.push((row.bucket, limit, left - 1.0));
+                    }
+                }
+
+                if retry.is_some() {
+                    return Ok(retry);
+                }
+
+                for (name, limit, left) in taken {
+                    update(rate_limits.filter(bucket.eq(name)))
+                        .set((
+                            tokens.eq(left),
+                            updated.eq(ts),
+                            full_at.eq(limit.full_at(left, ts)),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok(None)
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Give back a token taken from each of the given buckets, as for a
+    /// report which failed to be inserted.
+    ///
+    async fn refund_tokens(&self, buckets: Vec<(String, RateLimit)>) -> Result<(), Box<dyn Error>> {
+        use schema::rate_limits::dsl::*;
+
+        let ts = models::now();
+
+        let limits: HashMap<String, RateLimit> = buckets.into_iter().collect();
+
+        self.pool
+            .transaction(move |conn| {
+                // Locked in order so concurrent submits cannot deadlock.
+                let locked = rate_limits
+                    .filter(bucket.eq_any(limits.keys()))
+                    .order(bucket.asc())
+                    .for_update()
+                    .load::<RateBucket>(conn)?;
+
+                for row in locked {
+                    let limit = &limits[&row.bucket];
+                    let left = (limit.refill(row.tokens, row.updated, ts) + 1.0)
+                        .min(limit.capacity as f64);
+
+                    update(rate_limits.filter(bucket.eq(row.bucket)))
+                        .set((
+                            tokens.eq(left),
+                            updated.eq(ts),
+                            full_at.eq(limit.full_at(left, ts)),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Delete the buckets full again, skipping the ones being taken from.
+    ///
+    async fn prune_rate_limits(&self) -> Result<usize, Box<dyn Error>> {
+        let ts = models::now();
+
+        let res = self
+            .pool
+            .run(move |conn| {
+                diesel::sql_query(
+                    "DELETE FROM rate_limits WHERE bucket IN \
+                     (SELECT bucket FROM rate_limits WHERE full_at <= $1 FOR UPDATE SKIP LOCKED)",
+                )
+                .bind::<diesel::sql_types::Timestamptz, _>(ts)
+                .execute(conn)
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Rescore every active report in batches, notifying other instances
+    /// of the new priorities and returning how many changed.
+    ///
+    /// Waiting raises priorities without any event, so this is run
+    /// periodically.
+    ///
+    async fn refresh_priorities(&self) -> Result<usize, Box<dyn Error>> {
+        use schema::reports::dsl::*;
+
+        let mut last = 0;
+        let mut refreshed = 0;
+
+        loop {
+            let instance = self.instance;
+            let weights = self.priority.clone();
+            let ts = models::now();
+
+            let (rescored, batch) = self
+                .pool
+                .transaction(move |conn| {
+                    let rows = reports
+                        .filter(active.eq(true))
+                        .filter(id.gt(last))
+                        .order(id.asc())
+                        .limit(PRIORITY_BATCH_SIZE)
+                        .for_update()
+                        .skip_locked()
+                        .load::<ReportRow>(conn)?;
+
+                    let batch = rows.last().map(|row| (row.id, rows.len()));
+                    let rescored = rescore(conn, rows, &weights, ts)?;
+
+                    for report in &rescored {
+                        publish_change(conn, instance, OutboxKind::Rescore, report)?;
+                    }
+
+                    Ok((rescored, batch))
+                })
+                .await?;
+
+            refreshed += rescored.len();
+
+            for report in rescored {
+                self.insert_to_cache(report).await;
+            }
+
+            match batch {
+                Some((until, size)) if size as i64 == PRIORITY_BATCH_SIZE => last = until,
+                _ => return Ok(refreshed),
+            }
+        }
+    }
+
+    ///
+    /// Leave a note on a report, notifying other instances.
+    ///
+    /// `None` when the report does not exist.
+    ///
+    async fn add_note(
+        &self,
+        identifier: i64,
+        author: String,
+        body: String,
+    ) -> Result<Option<ReportNote>, Box<dyn Error>> {
+        use schema::report_notes::dsl::report_notes;
+        use schema::reports::dsl::*;
+
+        let instance = self.instance;
+
+        let note = NewReportNote {
+            report_id: identifier,
+            author,
+            body,
+            created: models::now(),
+        };
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                // Keeps the report from being removed until the note is in.
+                let found = reports
+                    .filter(id.eq(identifier))
+                    .select(id)
+                    .for_share()
+                    .first::<i64>(conn)
+                    .optional()?;
+
+                if found.is_none() {
+                    return Ok(None);
+                }
+
+                let inserted = insert_into(report_notes)
+                    .values(note)
+                    .get_result::<ReportNote>(conn)?;
+
+                notify::notify(
+                    conn,
+                    &ChangeNotification {
+                        instance,
+                        kind: OutboxKind::Note,
+                        id: inserted.id,
+                    },
+                )?;
+
+                Ok(Some(inserted))
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    async fn note(&self, identifier: i64) -> Result<Option<ReportNote>, Box<dyn Error>> {
+        use schema::report_notes::dsl::*;
+
+        let res = report_notes
+            .filter(id.eq(identifier))
+            .load_async::<ReportNote>(&self.pool)
+            .await?;
+
+        Ok(res.into_iter().next())
+    }
+
+    ///
+    /// Change the body of a note, keeping the previous one as an edit.
+    ///
+    /// `None` when the note does not exist.
+    ///
+    async fn edit_note(
+        &self,
+        identifier: i64,
+        editor: String,
+        new_body: String,
+    ) -> Result<Option<ReportNote>, Box<dyn Error>> {
+        use schema::report_note_edits::dsl::report_note_edits;
+        use schema::report_notes::dsl::*;
+
+        let ts = models::now();
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                let target = report_notes.filter(id.eq(identifier));
+
+                let before = match target.for_update().first::<ReportNote>(conn).optional()? {
+                    Some(val) => val,
+                    None => return Ok(None),
+                };
+
+                insert_into(report_note_edits)
+                    .values(NewNoteEdit {
+                        note_id: before.id,
+                        editor,
+                        timestamp: ts,
+                        body: before.body,
+                    })
+                    .execute(conn)?;
+
+                let note = update(target)
+                    .set((body.eq(new_body), edited.eq(ts)))
+                    .get_result::<ReportNote>(conn)?;
+
+                Ok(Some(note))
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Notes of reports, oldest first.
+    ///
+    async fn report_notes(&self, ids: Vec<i64>) -> Result<Vec<ReportNote>, Box<dyn Error>> {
+        use schema::report_notes::dsl::*;
+
+        let res = report_notes
+            .filter(report_id.eq_any(ids))
+            .order(id.asc())
+            .load_async::<ReportNote>(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Edits of notes, oldest first.
+    ///
+    async fn note_edits(&self, ids: Vec<i64>) -> Result<Vec<NoteEdit>, Box<dyn Error>> {
+        use schema::report_note_edits::dsl::*;
+
+        let res = report_note_edits
+            .filter(note_id.eq_any(ids))
+            .order(id.asc())
+            .load_async::<NoteEdit>(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Claim up to `limit` outbox events due for delivery.
+    ///
+    /// Claimed events count an attempt and are leased for
+    /// `OUTBOX_LEASE_SECS`, so concurrent dispatchers skip them. Only the
+    /// oldest pending event of each report is claimed, keeping
+    /// broadcasts of a report in order. Dead letters are skipped.
+    ///
+    async fn claim_outbox(&self, limit: i64) -> Result<Vec<OutboxEvent>, Box<dyn Error>> {
+        let res = self
+            .pool
+            .run(move |conn| {
+                diesel::sql_query(
+                    "UPDATE report_outbox SET attempts = attempts + 1, \
+                         next_attempt = now() + $1 * interval '1 second' \
+                     WHERE id IN ( \
+                         SELECT o.id FROM report_outbox o \
+                         WHERE o.next_attempt <= now() AND o.failed_at IS NULL \
+                         AND NOT EXISTS ( \
+                             SELECT 1 FROM report_outbox p \
+                             WHERE p.report_id = o.report_id AND p.id < o.id \
+                             AND p.failed_at IS NULL) \
+                         ORDER BY o.id LIMIT $2 \
+                         FOR UPDATE SKIP LOCKED) \
+                     RETURNING *",
+                )
+                .bind::<diesel::sql_types::Double, _>(OUTBOX_LEASE_SECS as f64)
+                .bind::<diesel::sql_types::BigInt, _>(limit)
+                .load::<OutboxEvent>(conn)
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Remove a delivered outbox event.
+    ///
+    async fn complete_outbox(&self, identifier: i64) -> Result<(), Box<dyn Error>> {
+        use schema::report_outbox::dsl::*;
+
+        diesel::delete(report_outbox.filter(id.eq(identifier)))
+            .execute_async(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Schedule the next attempt of a partially delivered outbox event.
+    ///
+    async fn retry_outbox(
+        &self,
+        identifier: i64,
+        endpoints: Vec<String>,
+        error: String,
+        at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error>> {
+        use schema::report_outbox::dsl::*;
+
+        update(report_outbox.filter(id.eq(identifier)))
+            .set((
+                delivered_to.eq(endpoints),
+                last_error.eq(error),
+                next_attempt.eq(at),
+            ))
+            .execute_async(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Leave an outbox event which ran out of attempts as a dead letter,
+    /// no longer claimed nor holding back later events of its report.
+    ///
+    async fn fail_outbox(
+        &self,
+        identifier: i64,
+        endpoints: Vec<String>,
+        error: String,
+    ) -> Result<(), Box<dyn Error>> {
+        use schema::report_outbox::dsl::*;
+
+        update(report_outbox.filter(id.eq(identifier)))
+            .set((
+                delivered_to.eq(endpoints),
+                last_error.eq(error),
+                failed_at.eq(models::now()),
+            ))
+            .execute_async(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Register a transporter, or take over the registration of its address,
+    /// leased for `lease`.
+    ///
+    /// Registrations expired by then are removed.
+    ///
+    async fn register_transporter(
+        &self,
+        taddress: String,
+        tnode: String,
+        lease: Duration,
+    ) -> Result<TransporterRegistration, Box<dyn Error>> {
+        use schema::transporters::dsl::*;
+
+        let ts = models::now();
+        let until = ts + chrono::Duration::from_std(lease)?;
+
+        let registration = TransporterRegistration {
+            address: taddress,
+            node: tnode,
+            registered: ts,
+            heartbeat: ts,
+            expires: until,
+        };
+
+        let res = self
+            .pool
+            .transaction(move |conn| {
+                diesel::delete(transporters.filter(expires.le(ts))).execute(conn)?;
+
+                insert_into(transporters)
+                    .values(&registration)
+                    .on_conflict(address)
+                    .do_update()
+                    .set((
+                        node.eq(&registration.node),
+                        heartbeat.eq(ts),
+                        expires.eq(until),
+                    ))
+                    .get_result::<TransporterRegistration>(conn)
+            })
+            .await?;
+
+        Ok(res)
+    }
+
+    ///
+    /// Extend the lease of a registered transporter by `lease`.
+    ///
+    /// `None` when the transporter is not registered or its lease has
+    /// already expired.
+    ///
+    async fn renew_transporter(
+        &self,
+        taddress: String,
+        lease: Duration,
+    ) -> Result<Option<TransporterRegistration>, Box<dyn Error>> {
+        use schema::transporters::dsl::*;
+
+        let ts = models::now();
+        let until = ts + chrono::Duration::from_std(lease)?;
+
+        let res = update(
+            transporters
+                .filter(address.eq(taddress))
+                .filter(expires.gt(ts)),
+        )
+        .set((heartbeat.eq(ts), expires.eq(until)))
+        .get_results_async::<TransporterRegistration>(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().next())
+    }
+
+    ///
+    /// Transporters whose lease has not expired, oldest registration first.
+    ///
+    async fn live_transporters(&self) -> Result<Vec<TransporterRegistration>, Box<dyn Error>> {
+        use schema::transporters::dsl::*;
+
+        let res = transporters
+            .filter(expires.gt(models::now()))
+            .order((registered.asc(), address.asc()))
+            .load_async::<TransporterRegistration>(&self.pool)
+            .await?;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ReportReleaseRequest;
+
+    ///
+    /// Database of `DATABASE_URL`, `None` skipping the test without one.
+    ///
+    fn db() -> Option<PgReportDb> {
+        let url = dotenv::var("DATABASE_URL").ok()?;
+
+        let weights = PriorityWeights {
+            window: chrono::Duration::zero(),
+            reporters: 0.0,
+            accuracy: 0.0,
+            waiting_per_hour: 0.0,
+            max_waiting_hours: 0,
+            tags: HashMap::new(),
+        };
+
+        let db = PgReportDb::new(
+            &url,
+            2,
+            Duration::from_secs(5),
+            CacheMode::Disabled,
+            0,
+            weights,
+        )
+        .unwrap();
+
+        db.run_migrations().unwrap();
+
+        Some(db)
+    }
+
+    // Database calls block in place, which needs a multi-threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn claiming_missing_report_is_not_found() {
+        let db = match db() {
+            Some(val) => val,
+            None => return,
+        };
+
+        let outcome = db
+            .claim_report(i64::MAX, "handler".to_owned(), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, TransitionOutcome::NotFound);
+    }
+
+    // Database calls block in place, which needs a multi-threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn releasing_missing_report_is_not_found() {
+        let db = match db() {
+            Some(val) => val,
+            None => return,
+        };
+
+        let release = ReportReleaseRequest {
+            id: i64::MAX,
+            operator: "handler".to_owned(),
+            reason: None,
+            force: false,
+        };
+
+        let outcome = db
+            .transition_report(release.into(), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, TransitionOutcome::NotFound);
+    }
 }
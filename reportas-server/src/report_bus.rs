@@ -0,0 +1,132 @@
+use tokio::sync::broadcast;
+
+use crate::data::models::{OutboxKind, Report, ReportNote};
+
+use service::report1_0;
+use service::report1_0::report_broadcast::Operation;
+
+/// Amount of events buffered for each subscriber before it starts lagging.
+const BUS_CAPACITY: usize = 128;
+
+///
+/// A report change published on the `ReportBus`.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportEvent {
+    Insert(Report),
+    Deactivate(Report),
+    Reactivate(Report),
+    /// Move between active statuses.
+    Update(Report),
+    /// Merged into another report as a duplicate.
+    Merge(Report),
+    NoteAdded {
+        report: Report,
+        note: ReportNote,
+    },
+}
+
+impl ReportEvent {
+    ///
+    /// Event of a report change, `None` for note changes which carry the
+    /// note as well, see `ReportEvent::NoteAdded`, and for new priorities
+    /// which subscribers are not told of.
+    ///
+    pub fn new(kind: OutboxKind, report: Report) -> Option<Self> {
+        let event = match kind {
+            OutboxKind::Insert => ReportEvent::Insert(report),
+            OutboxKind::Deactivate => ReportEvent::Deactivate(report),
+            OutboxKind::Reactivate => ReportEvent::Reactivate(report),
+            OutboxKind::Update => ReportEvent::Update(report),
+            OutboxKind::Merge => ReportEvent::Merge(report),
+            OutboxKind::Note | OutboxKind::Rescore => return None,
+        };
+
+        Some(event)
+    }
+
+    pub fn report(&self) -> &Report {
+        match self {
+            ReportEvent::Insert(report) => report,
+            ReportEvent::Deactivate(report) => report,
+            ReportEvent::Reactivate(report) => report,
+            ReportEvent::Update(report) => report,
+            ReportEvent::Merge(report) => report,
+            ReportEvent::NoteAdded { report, .. } => report,
+        }
+    }
+}
+
+impl From<ReportEvent> for report1_0::ReportBroadcast {
+    fn from(f: ReportEvent) -> Self {
+        let operation = match f {
+            ReportEvent::Insert(report) => Operation::Insert(report.into()),
+            ReportEvent::Deactivate(report) => Operation::Deactivate(report.into()),
+            ReportEvent::Reactivate(report) => Operation::Reactivate(report.into()),
+            ReportEvent::Update(report) => Operation::Update(report.into()),
+            ReportEvent::Merge(report) => Operation::Merge(report.into()),
+            ReportEvent::NoteAdded { note, .. } => Operation::NoteAdded(note.into()),
+        };
+
+        Self {
+            operation: Some(operation),
+        }
+    }
+}
+
+///
+/// Decides which events a subscriber receives.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscribeFilter {
+    /// Receive every event.
+    Global,
+    /// Receive events originating from the given server node.
+    Node(String),
+}
+
+impl SubscribeFilter {
+    pub fn matches(&self, event: &ReportEvent) -> bool {
+        match self {
+            SubscribeFilter::Global => true,
+            SubscribeFilter::Node(node) => event.report().server.as_ref() == Some(node),
+        }
+    }
+}
+
+///
+/// In-process fan-out of report events to every connected subscriber.
+///
+/// Clones publish to the same subscribers.
+///
+#[derive(Clone)]
+pub struct ReportBus {
+    sender: broadcast::Sender<ReportEvent>,
+}
+
+impl ReportBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+
+        Self { sender }
+    }
+
+    ///
+    /// Publish an event to all current subscribers.
+    ///
+    /// Events published while nobody is subscribed are dropped.
+    ///
+    pub fn publish(&self, event: ReportEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ReportEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for ReportBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
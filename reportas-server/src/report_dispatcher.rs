@@ -0,0 +1,198 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use prost::Message;
+use service::models::{now, OutboxEvent, OutboxKind};
+use service::report::IdentifiedReportMessage;
+use service::{PgReportDb, ReportDb, OUTBOX_MAX_ATTEMPTS};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+use crate::report_transporter::{TransportOutcome, Transporter};
+
+/// Largest amount of outbox events delivered at once.
+const DISPATCH_BATCH: i64 = 32;
+
+/// Time between outbox polls when not woken up.
+const DISPATCH_POLL: Duration = Duration::from_secs(1);
+
+/// Delay before the second attempt, doubled on every further attempt.
+const BACKOFF_BASE_SECS: i64 = 1;
+
+/// Longest delay between attempts.
+const BACKOFF_MAX_SECS: i64 = 300;
+
+///
+/// Delivers outbox broadcasts through the `Transporter` in the background.
+///
+/// Broadcasts go to the endpoints live when delivered. Failed deliveries
+/// are retried with exponential backoff, only to the endpoints that have
+/// not received the broadcast yet, and left as dead letters once out of
+/// attempts.
+///
+pub struct Dispatcher {
+    db: Arc<PgReportDb>,
+    transporter: Arc<Transporter>,
+    wake: Notify,
+}
+
+impl Dispatcher {
+    pub fn new(db: Arc<PgReportDb>, transporter: Arc<Transporter>) -> Self {
+        Self {
+            db,
+            transporter,
+            wake: Notify::new(),
+        }
+    }
+
+    ///
+    /// Deliver new outbox events without waiting for the next poll.
+    ///
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            let claimed = match self.db.claim_outbox(DISPATCH_BATCH).await {
+                Ok(val) => val,
+                Err(e) => {
+                    warn!("Claiming outbox events failed: {}", e);
+                    Vec::new()
+                }
+            };
+
+            let full = claimed.len() as i64 == DISPATCH_BATCH;
+
+            if !claimed.is_empty() {
+                self.dispatch_all(claimed).await;
+            }
+
+            if !full {
+                tokio::select! {
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(DISPATCH_POLL) => {}
+                }
+            }
+        }
+    }
+
+    async fn dispatch_all(&self, events: Vec<OutboxEvent>) {
+        // Errors are not `Send`, so stringify them before awaiting the retries.
+        let endpoints = match self
+            .transporter
+            .endpoints()
+            .await
+            .map_err(|e| e.to_string())
+        {
+            Ok(val) => val,
+            Err(error) => {
+                warn!("Loading transporter endpoints failed: {}", error);
+
+                for event in events.iter() {
+                    self.retry(event, event.delivered_to.clone(), error.clone())
+                        .await;
+                }
+
+                return;
+            }
+        };
+
+        join_all(
+            events
+                .into_iter()
+                .map(|event| self.dispatch(event, &endpoints)),
+        )
+        .await;
+    }
+
+    async fn dispatch(&self, event: OutboxEvent, endpoints: &[String]) {
+        let irm = match IdentifiedReportMessage::decode(&event.payload[..]) {
+            Ok(val) => val,
+            Err(e) => {
+                return self
+                    .retry(&event, event.delivered_to.clone(), e.to_string())
+                    .await
+            }
+        };
+
+        let targets: Vec<String> = endpoints
+            .iter()
+            .filter(|endpoint| !event.delivered_to.contains(endpoint))
+            .cloned()
+            .collect();
+
+        let results = match event.kind.parse::<OutboxKind>() {
+            Ok(OutboxKind::Insert) => self.transporter.transport(irm, &targets).await,
+            Ok(OutboxKind::Deactivate) => self.transporter.deactivate(irm, &targets).await,
+            Ok(OutboxKind::Reactivate) => self.transporter.reactivate(irm, &targets).await,
+            // Never written, updates, notes, merges and new priorities are not broadcast.
+            Ok(OutboxKind::Update)
+            | Ok(OutboxKind::Note)
+            | Ok(OutboxKind::Merge)
+            | Ok(OutboxKind::Rescore) => Vec::new(),
+            Err(e) => {
+                return self
+                    .retry(&event, event.delivered_to.clone(), e.to_owned())
+                    .await
+            }
+        };
+
+        let mut delivered = event.delivered_to.clone();
+        let mut errors = Vec::new();
+
+        for res in results {
+            match res.outcome {
+                TransportOutcome::Delivered => delivered.push(res.endpoint),
+                // Counted as delivered, there being nothing to retry.
+                TransportOutcome::Skipped => delivered.push(res.endpoint),
+                outcome => errors.push(format!("{} :: {}", res.endpoint, outcome)),
+            }
+        }
+
+        if !errors.is_empty() {
+            return self.retry(&event, delivered, errors.join("; ")).await;
+        }
+
+        info!(
+            "Outbox event {} ({}) of report {} delivered",
+            event.id, event.kind, event.report_id
+        );
+
+        if let Err(e) = self.db.complete_outbox(event.id).await {
+            warn!("Completing outbox event {} failed: {}", event.id, e);
+        }
+    }
+
+    async fn retry(&self, event: &OutboxEvent, delivered: Vec<String>, error: String) {
+        if event.attempts >= OUTBOX_MAX_ATTEMPTS {
+            error!(
+                "Giving up on outbox event {} ({}) of report {} after {} attempts: {}",
+                event.id, event.kind, event.report_id, event.attempts, error
+            );
+
+            if let Err(e) = self.db.fail_outbox(event.id, delivered, error).await {
+                warn!("Failing outbox event {} failed: {}", event.id, e);
+            }
+
+            return;
+        }
+
+        let at = now() + backoff(event.attempts);
+
+        if let Err(e) = self.db.retry_outbox(event.id, delivered, error, at).await {
+            warn!("Rescheduling outbox event {} failed: {}", event.id, e);
+        }
+    }
+}
+
+///
+/// Delay after the given amount of failed attempts.
+///
+fn backoff(attempts: i32) -> chrono::Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    let secs = BACKOFF_BASE_SECS.saturating_mul(2_i64.pow(exponent));
+
+    chrono::Duration::seconds(secs.min(BACKOFF_MAX_SECS))
+}
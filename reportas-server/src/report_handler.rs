@@ -1,92 +1,516 @@
-use crate::{data::models::*, report_transporter::Transporter};
-use service::{PgReportDb, QueryType, ReportDb};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{Config, RateLimits};
+use crate::data::models::*;
+use crate::report_bus::{ReportBus, ReportEvent};
+use crate::report_dispatcher::Dispatcher;
+use crate::report_transporter::Transporter;
+use service::cache::Coverage;
+use service::{
+    CacheMode, DuplicatePolicy, Page, PageToken, PgReportDb, RateLimit, ReportDb, ReportFilter,
+    ReportFilterSet, ReportOrder, ReportPage,
+};
 use thiserror::Error;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Uri;
+use tracing::{info, warn};
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("database failed")]
     DatabaseFailed,
-    #[error("transport error")]
-    TransportError,
     #[error("invalid timestamp")]
     InvalidTimestamp,
+    #[error("invalid page token")]
+    InvalidPageToken,
+    #[error("report not found")]
+    ReportNotFound,
+    #[error("note not found")]
+    NoteNotFound,
+    #[error("notes can only be edited by their author")]
+    NotNoteAuthor,
+    #[error("cannot move report from {from} to {to}")]
+    IllegalTransition {
+        from: ReportStatus,
+        to: ReportStatus,
+    },
+    #[error("missing resolution outcome")]
+    MissingOutcome,
+    #[error("report is claimed by {0}")]
+    ReportClaimed(String),
+    #[error("cannot merge a report into itself or its duplicates")]
+    MergeIntoItself,
+    #[error("rate limit exceeded, retry after {}ms", .0.as_millis())]
+    RateLimited(Duration),
+    #[error("invalid transporter address")]
+    InvalidTransporterAddress,
+    #[error("transporter not registered")]
+    TransporterNotRegistered,
+}
+
+///
+/// Queried reports, streamed as they are read from the database.
+///
+pub type ReportStream = Pin<Box<dyn Stream<Item = Result<Report, Error>> + Send>>;
+
+///
+/// A streamed page of queried reports.
+///
+pub struct ReportStreamPage {
+    pub reports: ReportStream,
+    /// Token of the next page, `None` on the last page or when unpaged.
+    pub next: Option<PageToken>,
+}
+
+///
+/// Transporters currently broadcast to.
+///
+pub struct TransporterList {
+    pub configured: Vec<String>,
+    pub registered: Vec<TransporterRegistration>,
+}
+
+///
+/// A note along with its edits, oldest first.
+///
+pub struct NoteThread {
+    pub note: ReportNote,
+    pub edits: Vec<NoteEdit>,
+}
+
+/// Delay before listening again after losing the change listener.
+const RELISTEN_DELAY: Duration = Duration::from_secs(1);
+
+/// Interval claims are checked for having run out at.
+const CLAIM_SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval full rate limit buckets are pruned at.
+const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+///
+/// Keep the cache coherent with report changes of every instance sharing
+/// the database, publishing the ones of other instances to `bus`.
+///
+/// The cache is warmed whenever listening starts and cleared whenever
+/// listening stops, as changes may be missed meanwhile.
+///
+async fn follow_changes(db: Arc<PgReportDb>, config: Config, bus: ReportBus) {
+    loop {
+        // Errors are not `Send`, so stringify them before awaiting.
+        let mut listener = match db.listen().await.map_err(|e| e.to_string()) {
+            Ok(val) => val,
+            Err(e) => {
+                warn!("Listening to report changes failed: {}", e);
+                tokio::time::sleep(RELISTEN_DELAY).await;
+                continue;
+            }
+        };
+
+        match db.warm_cache().await.map_err(|e| e.to_string()) {
+            Ok(Coverage::Partial) if matches!(config.cache, CacheMode::Active | CacheMode::All) => {
+                warn!(
+                    "Reports of cache mode {:?} exceed the cache capacity of {}, caching partially",
+                    config.cache, config.cache_capacity
+                )
+            }
+            Ok(coverage) => info!("Cache warmed with coverage {:?}", coverage),
+            Err(e) => warn!("Warming the cache failed: {}", e),
+        }
+
+        while let Some(change) = listener.recv().await {
+            // Notes leave the cached report as is.
+            if change.kind == OutboxKind::Note {
+                if !db.is_local(&change) {
+                    match note_added(&db, change.id).await.map_err(|e| e.to_string()) {
+                        Ok(Some(event)) => bus.publish(event),
+                        Ok(None) => {}
+                        Err(e) => warn!("Loading note ({}) failed: {}", change, e),
+                    }
+                }
+
+                continue;
+            }
+
+            match db.apply_change(change).await.map_err(|e| e.to_string()) {
+                Ok(Some(report)) if !db.is_local(&change) => {
+                    if let Some(event) = ReportEvent::new(change.kind, report) {
+                        bus.publish(event)
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Applying report change ({}) failed: {}", change, e);
+                    break;
+                }
+            }
+        }
+
+        warn!("Stopped listening to report changes, clearing the cache");
+
+        db.clear_cache().await;
+        tokio::time::sleep(RELISTEN_DELAY).await;
+    }
+}
+
+///
+/// Event of a note added by another instance, `None` when the note or its
+/// report no longer exists.
+///
+async fn note_added(
+    db: &PgReportDb,
+    id: i64,
+) -> Result<Option<ReportEvent>, Box<dyn std::error::Error>> {
+    let note = match db.note(id).await? {
+        Some(val) => val,
+        None => return Ok(None),
+    };
+
+    let filters = ReportFilterSet::all().with(ReportFilter::Id(note.report_id));
+
+    let event = db
+        .query_report(filters, ReportOrder::default())
+        .await?
+        .pop()
+        .map(|report| ReportEvent::NoteAdded { report, note });
+
+    Ok(event)
+}
+
+///
+/// Reopen reports whose claim ran out, publishing them to `bus`.
+///
+async fn release_claims(db: Arc<PgReportDb>, bus: ReportBus) {
+    let mut interval = tokio::time::interval(CLAIM_SWEEP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match db.release_expired_claims().await.map_err(|e| e.to_string()) {
+            Ok(released) => {
+                for report in released {
+                    info!("Claim on report {} ran out, reopening", report.id);
+                    bus.publish(ReportEvent::Update(report));
+                }
+            }
+            Err(e) => warn!("Releasing expired claims failed: {}", e),
+        }
+    }
+}
+
+///
+/// Delete rate limit buckets full again, which count as missing ones.
+///
+async fn prune_rate_limits(db: Arc<PgReportDb>) {
+    let mut interval = tokio::time::interval(RATE_LIMIT_PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = db.prune_rate_limits().await.map_err(|e| e.to_string()) {
+            warn!("Pruning rate limits failed: {}", e);
+        }
+    }
+}
+
+///
+/// Rescore every active report each `every`, as waiting raises priorities.
+///
+async fn refresh_priorities(db: Arc<PgReportDb>, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+
+        match db.refresh_priorities().await.map_err(|e| e.to_string()) {
+            Ok(0) => {}
+            Ok(refreshed) => info!("Refreshed the priority of {} reports", refreshed),
+            Err(e) => warn!("Refreshing priorities failed: {}", e),
+        }
+    }
 }
 
 /// Handle reports.
 pub struct ReportHandler {
-    db: PgReportDb,
-    transporter: Transporter,
+    db: Arc<PgReportDb>,
+    dispatcher: Arc<Dispatcher>,
+    transporter: Arc<Transporter>,
+    transporter_lease: Duration,
+    claim_lease: Duration,
+    duplicates: Option<DuplicatePolicy>,
+    rate_limits: RateLimits,
+    bus: ReportBus,
 }
 
 impl ReportHandler {
-    pub async fn new(addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let db = PgReportDb::new(addr).unwrap();
+    pub async fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let db = PgReportDb::new(
+            config.database_url(),
+            config.pool_size,
+            config.timeouts.database(),
+            config.cache,
+            config.cache_capacity,
+            config.priority.weights(),
+        )?;
+
+        db.run_migrations()?;
+
+        let db = Arc::new(db);
+        let bus = ReportBus::new();
+
+        tokio::spawn(follow_changes(db.clone(), config.clone(), bus.clone()));
+        tokio::spawn(release_claims(db.clone(), bus.clone()));
+        tokio::spawn(prune_rate_limits(db.clone()));
+
+        if let Some(every) = config.priority.refresh() {
+            tokio::spawn(refresh_priorities(db.clone(), every));
+        }
+
+        let addrs = config.endpoints.iter().map(|x| x.uri()).collect();
 
-        db.load_to_cache(false).await?;
+        let transporter = Arc::new(Transporter::new(
+            db.clone(),
+            addrs,
+            config.timeouts.transport_connect(),
+            config.timeouts.transport(),
+        )?);
 
-        let addrs = vec!["http://[::1]:50024", "http://[::1]:50025"];
+        let dispatcher = Arc::new(Dispatcher::new(db.clone(), transporter.clone()));
 
-        let transporter = Transporter::new(addrs).await?;
+        tokio::spawn(dispatcher.clone().run());
 
         Ok(ReportHandler {
             db,
+            dispatcher,
             transporter,
+            transporter_lease: config.timeouts.transporter_lease(),
+            claim_lease: config.timeouts.claim_lease(),
+            duplicates: config.duplicates.policy(),
+            rate_limits: config.rate_limits.clone(),
+            bus,
         })
     }
 
+    ///
+    /// Subscribe to inserted reports, status changes and added notes.
+    ///
+    pub fn subscribe(&self) -> broadcast::Receiver<ReportEvent> {
+        self.bus.subscribe()
+    }
+
+    ///
+    /// Insert a report, or link it to the active report it duplicates.
+    ///
+    /// Reports over a rate limit of their reporter, of their reporter
+    /// against the player or of their server node are refused.
+    ///
     pub async fn submit_report(&self, req: ReportRequest) -> Result<Report, Error> {
-        let utc = chrono::Utc::now();
-        let ts = utc.timestamp();
+        let taken = self.take_rate_limits(&req).await?;
 
-        let tags: Option<String>;
-        if req.tags.is_empty() {
-            tags = None;
-        } else {
-            tags = Some(req.tags)
-        }
+        let ts = now();
 
         let new_report = NewReport {
-            active: true,
+            status: ReportStatus::Open,
             timestamp: ts,
             reporter: req.reporter,
             reported: req.reported,
             description: req.desc,
-            tags,
+            tags: normalize_tags(req.tags),
+            server: req.server,
         };
 
-        let rep = match self.db.insert_report(new_report.clone()).await {
-            Ok(val) => val,
-            Err(_) => return Err(Error::DatabaseFailed),
+        // Errors are not `Send`, so stringify them before refunding.
+        let inserted = self
+            .db
+            .insert_report(new_report, self.duplicates)
+            .await
+            .map_err(|e| e.to_string());
+
+        let rep = match inserted {
+            Ok(InsertOutcome::Inserted(rep)) => *rep,
+            Ok(InsertOutcome::Duplicate(merged)) => {
+                info!(
+                    "Report {} duplicates report {}",
+                    merged.report.id, merged.parent.id
+                );
+
+                return Ok(self.publish_merge(*merged));
+            }
+            Err(_) => {
+                // Failed submits are not charged, so they can be retried.
+                if let Err(e) = self.db.refund_tokens(taken).await {
+                    warn!("Refunding rate limit tokens failed: {}", e);
+                }
+
+                return Err(Error::DatabaseFailed);
+            }
         };
 
-        match self.transporter.transport(rep.clone().into()).await {
-            Ok(_) => {}
-            Err(_) => return Err(Error::TransportError),
-        }
+        self.bus.publish(ReportEvent::Insert(rep.clone()));
+        self.dispatcher.wake();
 
         Ok(rep)
     }
 
+    ///
+    /// Take a token of every bucket the report falls in, returning the
+    /// buckets taken from.
+    ///
+    async fn take_rate_limits(
+        &self,
+        req: &ReportRequest,
+    ) -> Result<Vec<(String, RateLimit)>, Error> {
+        let limits = &self.rate_limits;
+        let mut buckets = Vec::new();
+
+        if limits.reporter.is_enabled() {
+            buckets.push((format!("reporter:{}", req.reporter), limits.reporter));
+        }
+
+        if limits.pair.is_enabled() {
+            buckets.push((
+                format!("pair:{}:{}", req.reporter, req.reported),
+                limits.pair,
+            ));
+        }
+
+        match &req.server {
+            Some(server) if limits.server.is_enabled() => {
+                buckets.push((format!("server:{}", server), limits.server));
+            }
+            _ => {}
+        }
+
+        if buckets.is_empty() {
+            return Ok(buckets);
+        }
+
+        match self.db.take_tokens(buckets.clone()).await {
+            Ok(None) => Ok(buckets),
+            Ok(Some(retry_after)) => Err(Error::RateLimited(retry_after)),
+            Err(_) => Err(Error::DatabaseFailed),
+        }
+    }
+
+    ///
+    /// Merge an active report into another as a duplicate.
+    ///
+    pub async fn merge_reports(&self, merge: ReportMerge) -> Result<MergedReport, Error> {
+        let merged = match self.db.merge_reports(merge).await {
+            Ok(MergeOutcome::Merged(val)) => *val,
+            Ok(MergeOutcome::NotFound) => return Err(Error::ReportNotFound),
+            Ok(MergeOutcome::IntoItself) => return Err(Error::MergeIntoItself),
+            Ok(MergeOutcome::Illegal(from)) => {
+                return Err(Error::IllegalTransition {
+                    from,
+                    to: ReportStatus::Duplicate,
+                })
+            }
+            Ok(MergeOutcome::Held(claimant)) => return Err(Error::ReportClaimed(claimant)),
+            Err(_) => return Err(Error::DatabaseFailed),
+        };
+
+        self.publish_merge(merged.clone());
+        self.dispatcher.wake();
+
+        Ok(merged)
+    }
+
+    fn publish_merge(&self, merged: MergedReport) -> Report {
+        self.bus.publish(ReportEvent::Merge(merged.report.clone()));
+        self.bus.publish(ReportEvent::Update(merged.parent));
+
+        merged.report
+    }
+
     pub async fn deactivate_report(&self, req: ReportDeactivateRequest) -> Result<Report, Error> {
-        let rep = match self
+        self.transition_report(req.into()).await
+    }
+
+    pub async fn reactivate_report(&self, req: ReportReactivateRequest) -> Result<Report, Error> {
+        self.transition_report(req.into()).await
+    }
+
+    ///
+    /// Release the claim on a report, reopening it.
+    ///
+    pub async fn release_report(&self, req: ReportReleaseRequest) -> Result<Report, Error> {
+        self.transition_report(req.into()).await
+    }
+
+    ///
+    /// Move a report to another status.
+    ///
+    /// Moves not allowed from the current status of the report are
+    /// rejected, see `ReportStatus::can_transition_to`, as are moves of
+    /// reports claimed by another handler unless forced. Closing a report
+    /// requires an outcome.
+    ///
+    pub async fn transition_report(&self, transition: ReportTransition) -> Result<Report, Error> {
+        let to = transition.status;
+
+        if !to.is_active() && transition.outcome.is_none() {
+            return Err(Error::MissingOutcome);
+        }
+
+        let outcome = self
             .db
-            .deactivate_report(req.id, req.operator, req.comment)
-            .await
-        {
-            Ok(val) => val,
+            .transition_report(transition, self.claim_lease)
+            .await;
+
+        self.publish_transition(outcome, to)
+    }
+
+    ///
+    /// Claim an active report for the length of a claim lease, or extend
+    /// the claim of the handler.
+    ///
+    pub async fn claim_report(&self, id: i64, handler: String) -> Result<Report, Error> {
+        let outcome = self.db.claim_report(id, handler, self.claim_lease).await;
+
+        self.publish_transition(outcome, ReportStatus::Claimed)
+    }
+
+    fn publish_transition(
+        &self,
+        outcome: Result<TransitionOutcome, Box<dyn std::error::Error>>,
+        to: ReportStatus,
+    ) -> Result<Report, Error> {
+        let (rep, kind) = match outcome {
+            Ok(TransitionOutcome::Moved(rep, kind)) => (*rep, kind),
+            Ok(TransitionOutcome::Illegal(from)) => {
+                return Err(Error::IllegalTransition { from, to })
+            }
+            Ok(TransitionOutcome::Held(claimant)) => return Err(Error::ReportClaimed(claimant)),
+            Ok(TransitionOutcome::NotFound) => return Err(Error::ReportNotFound),
             Err(_) => return Err(Error::DatabaseFailed),
         };
 
-        match self.transporter.deactivate(rep.clone().into()).await {
-            Ok(_) => {}
-            Err(_) => return Err(Error::TransportError),
+        if let Some(event) = ReportEvent::new(kind, rep.clone()) {
+            self.bus.publish(event);
         }
 
+        self.dispatcher.wake();
+
         Ok(rep)
     }
 
-    pub async fn query_all_reports(&self) -> Result<Vec<Report>, Error> {
-        let queried = match self.db.query_report(QueryType::ALL).await {
+    ///
+    /// Length of a claim lease.
+    ///
+    pub fn claim_lease(&self) -> Duration {
+        self.claim_lease
+    }
+
+    pub async fn query_reports(
+        &self,
+        filters: ReportFilterSet,
+        order: ReportOrder,
+    ) -> Result<Vec<Report>, Error> {
+        let queried = match self.db.query_report(filters, order).await {
             Ok(val) => val,
             Err(_) => return Err(Error::DatabaseFailed),
         };
@@ -94,15 +518,34 @@ impl ReportHandler {
         Ok(queried)
     }
 
-    pub async fn query_reports_by_reporter(
+    ///
+    /// Query reports a page at a time, or all at once when no page is given.
+    ///
+    /// Page tokens issued for a different order are rejected.
+    ///
+    pub async fn query_reports_page(
         &self,
-        query: ReportQuery,
-    ) -> Result<Vec<Report>, Error> {
-        let queried = match self
-            .db
-            .query_report(QueryType::ByReporter(query.query))
-            .await
-        {
+        filters: ReportFilterSet,
+        order: ReportOrder,
+        page: Option<Page>,
+    ) -> Result<ReportPage, Error> {
+        let page = match page {
+            Some(val) => val,
+            None => {
+                return Ok(ReportPage {
+                    reports: self.query_reports(filters, order).await?,
+                    next: None,
+                })
+            }
+        };
+
+        if let Some(after) = &page.after {
+            if after.order != order {
+                return Err(Error::InvalidPageToken);
+            }
+        }
+
+        let queried = match self.db.query_report_page(filters, order, page).await {
             Ok(val) => val,
             Err(_) => return Err(Error::DatabaseFailed),
         };
@@ -110,82 +553,366 @@ impl ReportHandler {
         Ok(queried)
     }
 
-    pub async fn query_reports_by_reported(
+    ///
+    /// Highlight the matches of the `Search` filters of a filter set in
+    /// queried reports.
+    ///
+    /// Empty when the filter set does not search.
+    ///
+    pub async fn search_snippets(
         &self,
-        query: ReportQuery,
-    ) -> Result<Vec<Report>, Error> {
-        let queried = match self
-            .db
-            .query_report(QueryType::ByReported(query.query))
-            .await
-        {
+        filters: &ReportFilterSet,
+        reports: &[Report],
+    ) -> Result<HashMap<i64, String>, Error> {
+        let search = match filters.search() {
+            Some(val) => val,
+            None => return Ok(HashMap::new()),
+        };
+
+        let ids = reports.iter().map(|rep| rep.id).collect();
+
+        let snippets = match self.db.search_snippets(search, ids).await {
             Ok(val) => val,
             Err(_) => return Err(Error::DatabaseFailed),
         };
 
-        Ok(queried)
+        Ok(snippets)
+    }
+
+    ///
+    /// Stream every report matching a filter set straight from the database.
+    ///
+    /// Database errors end the stream after being yielded.
+    ///
+    pub fn stream_reports(&self, filters: ReportFilterSet, order: ReportOrder) -> ReportStream {
+        Box::pin(
+            self.db
+                .stream_report(filters, order)
+                .map(|res| res.map_err(|_| Error::DatabaseFailed)),
+        )
+    }
+
+    ///
+    /// Stream the page requested by a legacy query.
+    ///
+    /// Unpaged queries are streamed page by page instead of being loaded
+    /// at once.
+    ///
+    async fn query_legacy_page(
+        &self,
+        filters: ReportFilterSet,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        if query.page_size <= 0 {
+            return Ok(ReportStreamPage {
+                reports: self.stream_reports(filters, ReportOrder::default()),
+                next: None,
+            });
+        }
+
+        let page = match Page::new(query.page_size, &query.page_token) {
+            Some(val) => val,
+            None => return Err(Error::InvalidPageToken),
+        };
+
+        let queried = self
+            .query_reports_page(filters, ReportOrder::default(), Some(page))
+            .await?;
+
+        Ok(ReportStreamPage {
+            reports: Box::pin(tokio_stream::iter(queried.reports.into_iter().map(Ok))),
+            next: queried.next,
+        })
+    }
+
+    pub async fn query_all_reports(&self, query: ReportQuery) -> Result<ReportStreamPage, Error> {
+        self.query_legacy_page(ReportFilterSet::all(), query).await
+    }
+
+    pub async fn query_reports_by_reporter(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let filters = ReportFilterSet::all().with(ReportFilter::Reporter(query.query.clone()));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    pub async fn query_reports_by_reported(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let filters = ReportFilterSet::all().with(ReportFilter::Reported(query.query.clone()));
+
+        self.query_legacy_page(filters, query).await
     }
 
     pub async fn query_reports_by_timestamp(
         &self,
         query: ReportQuery,
-    ) -> Result<Vec<Report>, Error> {
-        let ts = match query.query.parse::<i64>() {
-            Ok(val) => val,
-            Err(_) => return Err(Error::InvalidTimestamp),
+    ) -> Result<ReportStreamPage, Error> {
+        let ts = match query.query.parse::<i64>().ok().and_then(from_epoch_seconds) {
+            Some(val) => val,
+            None => return Err(Error::InvalidTimestamp),
+        };
+
+        let filters = ReportFilterSet::all().with(ReportFilter::InsertTimestamp(ts));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    pub async fn query_reports_by_id(&self, query: ReportQuery) -> Result<Vec<Report>, Error> {
+        self.query_reports(
+            ReportFilterSet::all().with(ReportFilter::Id(query.id)),
+            ReportOrder::default(),
+        )
+        .await
+    }
+
+    pub async fn query_reports_by_handler(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let filters = ReportFilterSet::all().with(ReportFilter::Handler(Some(query.query.clone())));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    pub async fn query_reports_by_handle_timestamp(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let ts = match query.query.parse::<i64>().ok().and_then(from_epoch_seconds) {
+            Some(val) => val,
+            None => return Err(Error::InvalidTimestamp),
         };
 
-        let queried = match self.db.query_report(QueryType::ByTimestamp(ts)).await {
+        let filters = ReportFilterSet::all().with(ReportFilter::HandleTimestamp(ts));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    pub async fn query_reports_by_server(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let server = if !query.query.is_empty() {
+            Some(query.query.clone())
+        } else {
+            None
+        };
+
+        let filters = ReportFilterSet::all().with(ReportFilter::Server(server));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    pub async fn query_reports_by_active(
+        &self,
+        query: ReportQuery,
+    ) -> Result<ReportStreamPage, Error> {
+        let filters = ReportFilterSet::all().with(ReportFilter::Active(true));
+
+        self.query_legacy_page(filters, query).await
+    }
+
+    ///
+    /// Every state change of a report, oldest first.
+    ///
+    pub async fn report_history(&self, id: i64) -> Result<Vec<HistoryEvent>, Error> {
+        let history = match self.db.report_history(id).await {
             Ok(val) => val,
             Err(_) => return Err(Error::DatabaseFailed),
         };
 
-        Ok(queried)
+        // Every report has at least its insert in the history.
+        if history.is_empty() {
+            return Err(Error::ReportNotFound);
+        }
+
+        Ok(history)
     }
 
-    pub async fn query_reports_by_id(&self, query: ReportQuery) -> Result<Vec<Report>, Error> {
-        let queried = match self.db.query_report(QueryType::ById(query.id)).await {
-            Ok(val) => val,
+    ///
+    /// Leave a note on a report, publishing it to subscribers.
+    ///
+    pub async fn add_note(
+        &self,
+        report_id: i64,
+        author: String,
+        body: String,
+    ) -> Result<ReportNote, Error> {
+        let note = match self.db.add_note(report_id, author, body).await {
+            Ok(Some(val)) => val,
+            Ok(None) => return Err(Error::ReportNotFound),
             Err(_) => return Err(Error::DatabaseFailed),
         };
 
-        Ok(queried)
+        let filters = ReportFilterSet::all().with(ReportFilter::Id(report_id));
+
+        if let Some(report) = self
+            .query_reports(filters, ReportOrder::default())
+            .await?
+            .pop()
+        {
+            self.bus.publish(ReportEvent::NoteAdded {
+                report,
+                note: note.clone(),
+            });
+        }
+
+        Ok(note)
     }
 
-    pub async fn query_reports_by_handler(&self, query: ReportQuery) -> Result<Vec<Report>, Error> {
-        let queried = match self
-            .db
-            .query_report(QueryType::ByHandler(query.query))
-            .await
+    ///
+    /// Notes of a report, oldest first.
+    ///
+    pub async fn report_notes(&self, report_id: i64) -> Result<Vec<ReportNote>, Error> {
+        match self.db.report_notes(vec![report_id]).await {
+            Ok(val) => Ok(val),
+            Err(_) => Err(Error::DatabaseFailed),
+        }
+    }
+
+    ///
+    /// Notes of a report along with their edits when asked for, oldest
+    /// first.
+    ///
+    pub async fn list_notes(
+        &self,
+        report_id: i64,
+        include_edits: bool,
+    ) -> Result<Vec<NoteThread>, Error> {
+        let filters = ReportFilterSet::all().with(ReportFilter::Id(report_id));
+
+        if self
+            .query_reports(filters, ReportOrder::default())
+            .await?
+            .is_empty()
         {
-            Ok(val) => val,
+            return Err(Error::ReportNotFound);
+        }
+
+        let notes = self.report_notes(report_id).await?;
+
+        let mut edits: HashMap<i64, Vec<NoteEdit>> = HashMap::new();
+
+        if include_edits && !notes.is_empty() {
+            let ids = notes.iter().map(|note| note.id).collect();
+
+            let queried = match self.db.note_edits(ids).await {
+                Ok(val) => val,
+                Err(_) => return Err(Error::DatabaseFailed),
+            };
+
+            for edit in queried {
+                edits.entry(edit.note_id).or_default().push(edit);
+            }
+        }
+
+        Ok(notes
+            .into_iter()
+            .map(|note| NoteThread {
+                edits: edits.remove(&note.id).unwrap_or_default(),
+                note,
+            })
+            .collect())
+    }
+
+    ///
+    /// Change the body of a note, keeping its previous body as an edit.
+    ///
+    /// Only the author of a note can edit it.
+    ///
+    pub async fn edit_note(
+        &self,
+        id: i64,
+        editor: String,
+        body: String,
+    ) -> Result<ReportNote, Error> {
+        let note = match self.db.note(id).await {
+            Ok(Some(val)) => val,
+            Ok(None) => return Err(Error::NoteNotFound),
             Err(_) => return Err(Error::DatabaseFailed),
         };
 
-        Ok(queried)
+        if note.author != editor {
+            return Err(Error::NotNoteAuthor);
+        }
+
+        match self.db.edit_note(id, editor, body).await {
+            Ok(Some(val)) => Ok(val),
+            Ok(None) => Err(Error::NoteNotFound),
+            Err(_) => Err(Error::DatabaseFailed),
+        }
     }
 
-    pub async fn query_reports_by_handle_timestamp(
+    ///
+    /// Length of a transporter lease.
+    ///
+    pub fn transporter_lease(&self) -> Duration {
+        self.transporter_lease
+    }
+
+    ///
+    /// Register the transporter of a game server node, or renew the
+    /// registration of its address.
+    ///
+    /// The address must be an absolute URI, e.g. `http://10.0.0.5:50024`.
+    ///
+    pub async fn register_transporter(
         &self,
-        query: ReportQuery,
-    ) -> Result<Vec<Report>, Error> {
-        let ts = match query.query.parse::<i64>() {
-            Ok(val) => val,
-            Err(_) => return Err(Error::InvalidTimestamp),
+        address: String,
+        node: String,
+    ) -> Result<TransporterRegistration, Error> {
+        let valid = match address.parse::<Uri>() {
+            Ok(uri) => uri.scheme().is_some() && uri.authority().is_some(),
+            Err(_) => false,
         };
 
-        let queried = match self.db.query_report(QueryType::ByHandleTimestamp(ts)).await {
-            Ok(val) => val,
-            Err(_) => return Err(Error::DatabaseFailed),
-        };
-        Ok(queried)
+        if !valid {
+            return Err(Error::InvalidTransporterAddress);
+        }
+
+        match self
+            .db
+            .register_transporter(address, node, self.transporter_lease)
+            .await
+        {
+            Ok(val) => Ok(val),
+            Err(_) => Err(Error::DatabaseFailed),
+        }
+    }
+
+    ///
+    /// Extend the lease of a registered transporter.
+    ///
+    /// Transporters whose lease has expired have to register again.
+    ///
+    pub async fn renew_transporter(
+        &self,
+        address: String,
+    ) -> Result<TransporterRegistration, Error> {
+        match self
+            .db
+            .renew_transporter(address, self.transporter_lease)
+            .await
+        {
+            Ok(Some(val)) => Ok(val),
+            Ok(None) => Err(Error::TransporterNotRegistered),
+            Err(_) => Err(Error::DatabaseFailed),
+        }
     }
 
-    pub async fn query_reports_by_active(&self) -> Result<Vec<Report>, Error> {
-        let queried = match self.db.query_report(QueryType::ByActive).await {
+    pub async fn list_transporters(&self) -> Result<TransporterList, Error> {
+        let registered = match self.db.live_transporters().await {
             Ok(val) => val,
             Err(_) => return Err(Error::DatabaseFailed),
         };
-        Ok(queried)
+
+        Ok(TransporterList {
+            configured: self.transporter.configured().to_vec(),
+            registered,
+        })
     }
 }
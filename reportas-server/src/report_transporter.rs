@@ -1,63 +1,355 @@
-use tonic::transport::Endpoint;
-use tracing::info;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use service::{PgReportDb, ReportDb};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+use tracing::{info, warn};
 
 use crate::report::report_transporter_client::ReportTransporterClient;
-use crate::report::IdentifiedReportMessage;
+use crate::report::{IdentifiedReportMessage, TransportStatus};
+
+///
+/// `TransportStatus.code` of a transporter that accepted a report.
+///
+pub const TRANSPORT_OK: i64 = 0;
+
+///
+/// Outcome of transporting a report to a single endpoint.
+///
+#[derive(Debug, Clone)]
+pub enum TransportOutcome {
+    Delivered,
+    /// The transporter answered with a non-zero `TransportStatus.code`.
+    Rejected(i64),
+    Failed(Status),
+    TimedOut,
+    /// The transporter predates the broadcast and answered `Unimplemented`.
+    Skipped,
+}
+
+impl fmt::Display for TransportOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportOutcome::Delivered => write!(f, "delivered"),
+            TransportOutcome::Rejected(code) => write!(f, "rejected with code {}", code),
+            TransportOutcome::Failed(status) => write!(f, "failed: {}", status),
+            TransportOutcome::TimedOut => write!(f, "timed out"),
+            TransportOutcome::Skipped => write!(f, "skipped, broadcast not implemented"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransportResult {
+    pub endpoint: String,
+    pub outcome: TransportOutcome,
+}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Broadcast {
+    Report,
+    Deactivate,
+    Reactivate,
+}
+
+///
+/// Fans reports out to report transporters at once.
+///
+/// Transporters are the ones of the configuration and the ones registered
+/// with a live lease. Each endpoint keeps a single lazily connected
+/// channel, which reconnects on its own after failures.
+///
 pub struct Transporter {
-    endpoints: Vec<Endpoint>,
+    db: Arc<PgReportDb>,
+    configured: Vec<String>,
+    clients: Mutex<HashMap<String, ReportTransporterClient<Channel>>>,
+    connect_timeout: Duration,
+    timeout: Duration,
 }
 
-#[allow(dead_code)]
 impl Transporter {
-    pub async fn new(addrs: Vec<&'static str>) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut endpoints = Vec::<Endpoint>::new();
+    pub fn new(
+        db: Arc<PgReportDb>,
+        configured: Vec<String>,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        for addr in configured.iter() {
+            Endpoint::from_shared(addr.clone())?;
+        }
+
+        Ok(Self {
+            db,
+            configured,
+            clients: Mutex::new(HashMap::new()),
+            connect_timeout,
+            timeout,
+        })
+    }
 
-        for addr in addrs.iter() {
-            let endpoint = Endpoint::from_static(addr);
-            endpoints.push(endpoint);
+    ///
+    /// Endpoints of the configuration.
+    ///
+    pub fn configured(&self) -> &[String] {
+        &self.configured
+    }
+
+    ///
+    /// Endpoints of the configuration followed by the ones registered with
+    /// a live lease.
+    ///
+    /// Channels of endpoints no longer present are closed.
+    ///
+    pub async fn endpoints(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut endpoints = self.configured.clone();
+
+        for registration in self.db.live_transporters().await? {
+            if !endpoints.contains(&registration.address) {
+                endpoints.push(registration.address);
+            }
         }
 
-        Ok(Self { endpoints })
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|endpoint, _| endpoints.contains(endpoint));
+
+        Ok(endpoints)
     }
 
-    pub async fn transport(
+    fn client(
         &self,
-        irm: IdentifiedReportMessage,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Attempting to transport to the following ENDPOINTS:");
+        endpoint: &str,
+    ) -> Result<ReportTransporterClient<Channel>, tonic::transport::Error> {
+        let mut clients = self.clients.lock().unwrap();
 
-        for endpoint in self.endpoints.iter() {
-            info!("{}", endpoint.uri());
+        if let Some(client) = clients.get(endpoint) {
+            return Ok(client.clone());
+        }
 
-            if let Ok(e) = endpoint.connect().await {
-                let mut client = ReportTransporterClient::new(e);
-                let request = tonic::Request::new(irm.clone());
+        let channel = Endpoint::from_shared(endpoint.to_owned())?
+            .connect_timeout(self.connect_timeout)
+            .connect_lazy();
 
-                let _status = client.broadcast_report(request).await?;
-            }
-        }
+        let client = ReportTransporterClient::new(channel);
+        clients.insert(endpoint.to_owned(), client.clone());
 
-        Ok(())
+        Ok(client)
+    }
+
+    pub async fn transport(
+        &self,
+        irm: IdentifiedReportMessage,
+        endpoints: &[String],
+    ) -> Vec<TransportResult> {
+        self.broadcast(Broadcast::Report, irm, endpoints).await
     }
 
     pub async fn deactivate(
         &self,
         irm: IdentifiedReportMessage,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Attempting to transport to the following ENDPOINTS:");
+        endpoints: &[String],
+    ) -> Vec<TransportResult> {
+        self.broadcast(Broadcast::Deactivate, irm, endpoints).await
+    }
+
+    pub async fn reactivate(
+        &self,
+        irm: IdentifiedReportMessage,
+        endpoints: &[String],
+    ) -> Vec<TransportResult> {
+        self.broadcast(Broadcast::Reactivate, irm, endpoints).await
+    }
+
+    ///
+    /// Send a report to the given endpoints concurrently, each given at
+    /// most `timeout` to answer.
+    ///
+    async fn broadcast(
+        &self,
+        kind: Broadcast,
+        irm: IdentifiedReportMessage,
+        endpoints: &[String],
+    ) -> Vec<TransportResult> {
+        info!(
+            "Attempting to transport ({:?}) to {} ENDPOINTS",
+            kind,
+            endpoints.len()
+        );
 
-        for endpoint in self.endpoints.iter() {
-            info!("{}", endpoint.uri());
+        let calls = endpoints.iter().map(|endpoint| {
+            let client = self.client(endpoint);
+            let irm = irm.clone();
 
-            if let Ok(e) = endpoint.connect().await {
-                let mut client = ReportTransporterClient::new(e);
-                let request = tonic::Request::new(irm.clone());
+            async move {
+                let outcome = match client {
+                    Ok(client) => send(client, kind, irm, self.timeout).await,
+                    Err(e) => TransportOutcome::Failed(Status::invalid_argument(e.to_string())),
+                };
 
-                let _status = client.broadcast_deactivate(request).await?;
+                if let TransportOutcome::Delivered = outcome {
+                    info!("{} :: {}", endpoint, outcome);
+                } else {
+                    warn!("{} :: {}", endpoint, outcome);
+                }
+
+                TransportResult {
+                    endpoint: endpoint.clone(),
+                    outcome,
+                }
             }
+        });
+
+        join_all(calls).await
+    }
+}
+
+///
+/// Send a report to a single transporter, given at most `timeout` to
+/// answer.
+///
+/// Transporters predating reactivation broadcasts answer them with
+/// `Unimplemented`, and are skipped instead of failing the broadcast for
+/// good. They are not sent the report again, which would duplicate it.
+///
+async fn send(
+    mut client: ReportTransporterClient<Channel>,
+    kind: Broadcast,
+    irm: IdentifiedReportMessage,
+    timeout: Duration,
+) -> TransportOutcome {
+    let call = async {
+        match kind {
+            Broadcast::Report => client.broadcast_report(irm).await,
+            Broadcast::Deactivate => client.broadcast_deactivate(irm).await,
+            Broadcast::Reactivate => client.broadcast_reactivate(irm).await,
+        }
+    };
+
+    match tokio::time::timeout(timeout, call).await {
+        Ok(Ok(res)) => outcome(res.into_inner()),
+        Ok(Err(status))
+            if kind == Broadcast::Reactivate && status.code() == Code::Unimplemented =>
+        {
+            TransportOutcome::Skipped
+        }
+        Ok(Err(status)) => TransportOutcome::Failed(status),
+        Err(_) => TransportOutcome::TimedOut,
+    }
+}
+
+fn outcome(status: TransportStatus) -> TransportOutcome {
+    if status.code == TRANSPORT_OK {
+        TransportOutcome::Delivered
+    } else {
+        TransportOutcome::Rejected(status.code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tonic::{Request, Response};
+
+    use super::*;
+    use crate::report::report_transporter_server::{ReportTransporter, ReportTransporterServer};
+
+    ///
+    /// A transporter predating reactivation broadcasts, counting the
+    /// reports it was sent.
+    ///
+    #[derive(Default)]
+    struct LegacyTransporter {
+        reports: Arc<AtomicUsize>,
+    }
+
+    #[tonic::async_trait]
+    impl ReportTransporter for LegacyTransporter {
+        async fn broadcast_report(
+            &self,
+            _: Request<IdentifiedReportMessage>,
+        ) -> Result<Response<TransportStatus>, Status> {
+            self.reports.fetch_add(1, Ordering::SeqCst);
+
+            Ok(Response::new(TransportStatus { code: TRANSPORT_OK }))
+        }
+
+        async fn broadcast_deactivate(
+            &self,
+            _: Request<IdentifiedReportMessage>,
+        ) -> Result<Response<TransportStatus>, Status> {
+            Ok(Response::new(TransportStatus { code: TRANSPORT_OK }))
+        }
+
+        async fn broadcast_reactivate(
+            &self,
+            _: Request<IdentifiedReportMessage>,
+        ) -> Result<Response<TransportStatus>, Status> {
+            Err(Status::unimplemented("not implemented"))
         }
+    }
+
+    async fn serve(transporter: LegacyTransporter) -> ReportTransporterClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let incoming = async_stream::stream! {
+            loop {
+                yield listener.accept().await.map(|(stream, _)| stream);
+            }
+        };
+
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(ReportTransporterServer::new(transporter))
+                .serve_with_incoming(incoming),
+        );
+
+        let channel = Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect_lazy();
+
+        ReportTransporterClient::new(channel)
+    }
+
+    #[tokio::test]
+    async fn reactivate_is_skipped_when_unimplemented() {
+        let transporter = LegacyTransporter::default();
+        let reports = transporter.reports.clone();
+        let client = serve(transporter).await;
+
+        let outcome = send(
+            client,
+            Broadcast::Reactivate,
+            IdentifiedReportMessage::default(),
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert!(matches!(outcome, TransportOutcome::Skipped));
+        assert_eq!(reports.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn deactivate_is_not_sent_as_report() {
+        let transporter = LegacyTransporter::default();
+        let reports = transporter.reports.clone();
+        let client = serve(transporter).await;
+
+        let outcome = send(
+            client,
+            Broadcast::Deactivate,
+            IdentifiedReportMessage::default(),
+            Duration::from_secs(5),
+        )
+        .await;
 
-        Ok(())
+        assert!(matches!(outcome, TransportOutcome::Delivered));
+        assert_eq!(reports.load(Ordering::SeqCst), 0);
     }
 }
@@ -3,15 +3,26 @@ extern crate dotenv;
 
 mod grpc;
 
+pub mod config;
+pub mod report_bus;
+pub mod report_dispatcher;
 pub mod report_handler;
 pub mod report_transporter;
 
+use config::Config;
 use grpc::report_handler::GrpcReportHandler;
+use grpc::report_handler1_0::GrpcReportHandler1_0;
+use report_handler::ReportHandler;
 
 use clap::{App, Arg};
 use service::*;
 
 use report::report_handler_server::ReportHandlerServer;
+use report1_0::report_handler_server::ReportHandlerServer as ReportHandlerServer1_0;
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 
 use tonic::transport::Server;
 use tracing::{debug, info, Level};
@@ -33,44 +44,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let matches = App::new("reportas-server")
         .version("0.1.0")
         .author("7Gv")
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .help("Given TOML configuration file, overridden by environment variables")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("address")
                 .short("a")
                 .long("address")
-                .required(true)
                 .value_name("ADDRESS")
-                .help("Given address for server to listen to")
+                .help("Given address for server to listen to, overrides the configuration")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("port")
                 .short("p")
                 .long("port")
-                .required(true)
                 .value_name("PORT")
-                .help("Given TCP port for server to listen to")
+                .help("Given TCP port for server to listen to, overrides the configuration")
                 .takes_value(true),
         )
         .get_matches();
 
-    let addr = format!(
-        "{}:{}",
-        matches.value_of("address").unwrap(),
-        matches.value_of("port").unwrap()
-    )
-    .parse()?;
+    let mut config = Config::load(matches.value_of("config").map(Path::new))?;
+
+    if let Some(address) = matches.value_of("address") {
+        config.listen_addr = address.to_owned();
+    }
+
+    if let Some(port) = matches.value_of("port") {
+        config.listen_port = port.parse()?;
+    }
+
+    debug!("config :: -> {:?}", &config);
+
+    let addr: SocketAddr = format!("{}:{}", config.listen_addr, config.listen_port).parse()?;
     debug!("addr :: -> {}", &addr);
 
-    let dburl = &dotenv::var("DATABASE_URL").unwrap();
-    debug!("DATABASE_URL :: -> {}", &dburl);
+    let handler = Arc::new(ReportHandler::new(&config).await?);
 
-    let report_handler = GrpcReportHandler::new(dburl).await?;
+    let report_handler = GrpcReportHandler::new(handler.clone());
+    let report_handler1_0 = GrpcReportHandler1_0::new(handler);
 
     info!("ReportHandler initiated");
     info!("LISTENING TO CHANNEL BEGUN: {}", &addr);
 
     Server::builder()
+        .timeout(config.timeouts.request())
         .add_service(ReportHandlerServer::new(report_handler))
+        .add_service(ReportHandlerServer1_0::new(report_handler1_0))
         .serve(addr)
         .await?;
 